@@ -0,0 +1,51 @@
+// Time perft and copying the board, e.g. to compare board representations.
+// cargo bench --bench perft
+
+extern crate rust_chess;
+
+use rust_chess::board::*;
+
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+
+/// Seconds as a float.
+fn seconds(elapsed: Duration) -> f64 {
+  elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9
+}
+
+/// Time perft to a depth and print how fast it went.
+fn perft(name: &str, fen: &str, depth: usize) {
+  let board = Board::from_fen(fen, Color::White).unwrap();
+
+  let start = Instant::now();
+  let nodes = board.perft(depth);
+  let elapsed = seconds(start.elapsed());
+
+  println!("perft {} depth {}: {} nodes in {:.3}s ({:.0} nodes/s)", name, depth, nodes, elapsed, nodes as f64 / elapsed);
+}
+
+/// Time copying the board and sending it over a channel, which the game
+/// does on every update.
+fn clone(times: u32) {
+  let board = Board::from_fen(KIWIPETE, Color::White).unwrap();
+  let (sender, receiver) = channel();
+
+  let start = Instant::now();
+
+  for _ in 0..times {
+    sender.send(board.clone()).unwrap();
+    receiver.recv().unwrap();
+  }
+
+  let elapsed = seconds(start.elapsed());
+
+  println!("clone and send: {} boards in {:.3}s ({:.0} ns each)", times, elapsed, elapsed * 1e9 / times as f64);
+}
+
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+fn main() {
+  perft("start", STARTING_FEN, 4);
+  perft("kiwipete", KIWIPETE, 3);
+  clone(100_000);
+}
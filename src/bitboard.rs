@@ -0,0 +1,992 @@
+/// Bitboards: the position as a 64-bit mask per piece type and color, one bit
+/// per square (a1 is bit 0, h1 bit 7 and h8 bit 63), with precomputed attacks.
+/// Rooks, bishops and queens look their attacks up with magic multiplication,
+/// or with PEXT on CPUs that have BMI2 (build with -C target-cpu=native).
+
+#[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))]
+use std::arch::x86_64::_pext_u64;
+
+use zobrist;
+
+/// A set of squares.
+pub type Bitboard = u64;
+
+/// Colors, to pick the masks of a side.
+pub const WHITE: usize = 0;
+pub const BLACK: usize = 1;
+
+/// Piece types, to pick the masks of a piece.
+pub const PAWN: usize = 0;
+pub const KNIGHT: usize = 1;
+pub const BISHOP: usize = 2;
+pub const ROOK: usize = 3;
+pub const QUEEN: usize = 4;
+pub const KING: usize = 5;
+
+/// Castling rights, one bit each.
+pub const WHITE_KING_SIDE: u8 = 1;
+pub const WHITE_QUEEN_SIDE: u8 = 2;
+pub const BLACK_KING_SIDE: u8 = 4;
+pub const BLACK_QUEEN_SIDE: u8 = 8;
+
+/// What pawns can become, best first.
+pub const PROMOTIONS: [usize; 4] = [QUEEN, ROOK, BISHOP, KNIGHT];
+
+/// Squares the same color as b1.
+pub const LIGHT_SQUARES: Bitboard = 0x55AA_55AA_55AA_55AA;
+
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const KNIGHT_OFFSETS: [(i32, i32); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+const KING_OFFSETS: [(i32, i32); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+
+/// Magic numbers for rooks, by square. Multiplying the pieces in the way by
+/// the magic packs them into a small index without collisions (see find_magic()
+/// in the tests, which found them).
+const ROOK_MAGICS: [u64; 64] = [
+  0x1080004008801020, 0x0840092002C03000, 0x1900200010400900, 0x0880100008000480,
+  0x4200100420080200, 0x8100020100080400, 0x0200040110886200, 0x0200008040220411,
+  0x0404800084400220, 0x0000401000402000, 0x0086001081220440, 0x0408800800100280,
+  0x000A001201040820, 0x8848800200840080, 0x4001000100040200, 0x0442000102105084,
+  0x9080010020804100, 0x0040404000201009, 0x0000808010002009, 0x2200090021D00100,
+  0x0008008008040080, 0x0004004002010040, 0x0011040008015042, 0x00000A0001768104,
+  0x0000800080204009, 0x2010004140002001, 0x9800200280100080, 0x1000100080080080,
+  0x0442000A00049020, 0x2100040080020080, 0x0800120400900148, 0x0010040A00128541,
+  0x2800804000800030, 0x1010002000400041, 0x4000200011004100, 0x0610008410800800,
+  0x0400802402800800, 0xC100020080800400, 0x0002000802000401, 0x0182085882000401,
+  0x0220204000808000, 0x2860100040024022, 0x0001002004110040, 0x99101042000A0020,
+  0x0004080004008080, 0x0010040002008080, 0x2012004881020004, 0x8300842444820011,
+  0x0088403882010200, 0x0820400080210100, 0x0110910040A00300, 0x0801100280080480,
+  0x0242009008200600, 0x1002000489500200, 0x0040800200010080, 0x0091800041000080,
+  0x0000209300488001, 0x04C1002414824001, 0x020020000B001041, 0x7000100004200901,
+  0x8002002004100802, 0x30010002084C0007, 0x0888221800813004, 0x4000002840840112,
+];
+
+/// Magic numbers for bishops, by square.
+const BISHOP_MAGICS: [u64; 64] = [
+  0xA010041108003100, 0x006082020A002900, 0x6810010619200000, 0x08281A0520000408,
+  0x0001104001000400, 0x0018901008048400, 0x00040A0210245280, 0x000200210808A402,
+  0x9140048410821200, 0x0800091010820041, 0x20504804832202C0, 0x0100091401081000,
+  0x8021011140000012, 0x0810020804450400, 0x208B0542109008A2, 0x0080084A08040204,
+  0x0040E2A80811244C, 0x2505022008008108, 0x0430220100420040, 0x010A040420220040,
+  0x1105000290400000, 0x0093001200822120, 0x4000A62048043004, 0x280120048A015004,
+  0x006090002A020814, 0x44042000240800D0, 0x01102800040A4400, 0x1004080080220040,
+  0x0001001011004024, 0x0010044000805040, 0x0914041200820100, 0x0004821012821480,
+  0x0024040500C05021, 0x0088611002080200, 0x0116080A00040020, 0x4000020080080080,
+  0x2450450140840040, 0x0000880201484100, 0x0222020404020092, 0x8081110600002E00,
+  0x2842101105000801, 0x1100809008001025, 0x00020202221C0400, 0x0422014022009020,
+  0x0210046102100C00, 0xC004008082029102, 0x00AA461801101200, 0x0404080080201108,
+  0x020542108C205002, 0x0410544804100100, 0x0040910841100000, 0x0400200042021100,
+  0x00004204850400C0, 0x0200100410A42102, 0x1040020801210102, 0x0805040410420000,
+  0x2884804130100200, 0x800C262201242000, 0x1058000194108800, 0x0014221054420204,
+  0x0104000012A02200, 0x0200881003300100, 0x0140400202840100, 0x0402020801010201,
+];
+
+/// The square of a file and a rank, both from 0.
+pub fn square(file: usize, rank: usize) -> usize {
+  rank * 8 + file
+}
+
+/// A bitboard with only this square.
+pub fn bit(square: usize) -> Bitboard {
+  1u64 << square
+}
+
+/// The squares of a bitboard, a1 first.
+pub fn squares(bitboard: Bitboard) -> Squares {
+  Squares(bitboard)
+}
+
+/// Iterator over the squares of a bitboard.
+pub struct Squares(Bitboard);
+
+impl Iterator for Squares {
+  type Item = usize;
+
+  fn next(&mut self) -> Option<usize> {
+    match self.0 {
+      0 => None,
+      bits => {
+        // Clear the lowest bit
+        self.0 = bits & (bits - 1);
+
+        Some(bits.trailing_zeros() as usize)
+      },
+    }
+  }
+}
+
+/// Where a sliding piece looks up its attacks.
+///
+/// Parameters:
+/// `mask`: Bitboard, the squares where a piece can be in the way (the edges can't block anything)
+/// `magic`: u64, see ROOK_MAGICS
+/// `shift`: u32, 64 minus the number of squares in the mask
+/// `offset`: usize, where this square's attacks start in the shared table
+struct Magic {
+  mask: Bitboard,
+  magic: u64,
+  shift: u32,
+  offset: usize,
+}
+
+impl Magic {
+  /// Work out the attacks for every way the mask can be filled and put them in the table.
+  fn new(square: usize, magic: u64, directions: &[(i32, i32)], table: &mut Vec<Bitboard>) -> Magic {
+    let mask = relevant_squares(square, directions);
+
+    let entry = Magic{
+      mask,
+      magic,
+      shift: 64 - mask.count_ones(),
+      offset: table.len(),
+    };
+
+    let size = table.len() + (1 << mask.count_ones());
+    table.resize(size, 0);
+
+    // Every subset of the mask (the Carry-Rippler trick)
+    let mut subset: Bitboard = 0;
+
+    loop {
+      table[entry.index(subset)] = slide(square, subset, directions);
+
+      subset = subset.wrapping_sub(mask) & mask;
+
+      if subset == 0 {
+        break;
+      }
+    }
+
+    entry
+  }
+
+  /// Where the attacks are in the table for these pieces on the board.
+  #[cfg(not(all(target_arch = "x86_64", target_feature = "bmi2")))]
+  fn index(&self, occupied: Bitboard) -> usize {
+    self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+  }
+
+  /// Where the attacks are in the table for these pieces on the board.
+  /// PEXT packs the bits of the mask together, no magic needed.
+  #[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))]
+  fn index(&self, occupied: Bitboard) -> usize {
+    self.offset + unsafe { _pext_u64(occupied, self.mask) } as usize
+  }
+}
+
+/// Attacks of every piece from every square, worked out once.
+struct Tables {
+  knight: Vec<Bitboard>,
+  king: Vec<Bitboard>,
+  pawn: [Vec<Bitboard>; 2],
+  rook: Vec<Magic>,
+  bishop: Vec<Magic>,
+  sliders: Vec<Bitboard>,
+}
+
+impl Tables {
+  fn new() -> Tables {
+    let mut sliders = Vec::new();
+
+    let rook = (0..64).map(|square| Magic::new(square, ROOK_MAGICS[square], &ROOK_DIRECTIONS, &mut sliders)).collect();
+    let bishop = (0..64).map(|square| Magic::new(square, BISHOP_MAGICS[square], &BISHOP_DIRECTIONS, &mut sliders)).collect();
+
+    Tables{
+      knight: (0..64).map(|square| leap(square, &KNIGHT_OFFSETS)).collect(),
+      king: (0..64).map(|square| leap(square, &KING_OFFSETS)).collect(),
+      pawn: [
+        (0..64).map(|square| leap(square, &[(-1, 1), (1, 1)])).collect(),
+        (0..64).map(|square| leap(square, &[(-1, -1), (1, -1)])).collect(),
+      ],
+      rook,
+      bishop,
+      sliders,
+    }
+  }
+}
+
+lazy_static! {
+  static ref TABLES: Tables = Tables::new();
+}
+
+/// Squares a piece jumping by these offsets reaches, e.g. a knight.
+fn leap(square: usize, offsets: &[(i32, i32)]) -> Bitboard {
+  let (file, rank) = ((square % 8) as i32, (square / 8) as i32);
+
+  offsets.iter()
+    .map(|&(df, dr)| (file + df, rank + dr))
+    .filter(|&(file, rank)| on_board(file, rank))
+    .fold(0, |attacks, (file, rank)| attacks | bit(square_of(file, rank)))
+}
+
+/// Attacks of a sliding piece, the slow way: walk every direction until
+/// something is in the way (which is attacked too).
+fn slide(square: usize, occupied: Bitboard, directions: &[(i32, i32)]) -> Bitboard {
+  let mut attacks = 0;
+
+  for &(df, dr) in directions.iter() {
+    let (mut file, mut rank) = ((square % 8) as i32 + df, (square / 8) as i32 + dr);
+
+    while on_board(file, rank) {
+      let target = bit(square_of(file, rank));
+      attacks |= target;
+
+      if occupied & target != 0 {
+        break;
+      }
+
+      file += df;
+      rank += dr;
+    }
+  }
+
+  attacks
+}
+
+/// Squares where a piece can block a slider: its rays without the last square.
+fn relevant_squares(square: usize, directions: &[(i32, i32)]) -> Bitboard {
+  let mut squares = 0;
+
+  for &(df, dr) in directions.iter() {
+    let (mut file, mut rank) = ((square % 8) as i32 + df, (square / 8) as i32 + dr);
+
+    while on_board(file + df, rank + dr) {
+      squares |= bit(square_of(file, rank));
+
+      file += df;
+      rank += dr;
+    }
+  }
+
+  squares
+}
+
+/// Check signed coordinates are on the board.
+fn on_board(file: i32, rank: i32) -> bool {
+  (0..8).contains(&file) && (0..8).contains(&rank)
+}
+
+/// square() for signed coordinates that are known to be on the board.
+fn square_of(file: i32, rank: i32) -> usize {
+  square(file as usize, rank as usize)
+}
+
+/// Squares a knight on `square` attacks.
+pub fn knight_attacks(square: usize) -> Bitboard {
+  TABLES.knight[square]
+}
+
+/// Squares a king on `square` attacks.
+pub fn king_attacks(square: usize) -> Bitboard {
+  TABLES.king[square]
+}
+
+/// Squares a pawn of `color` on `square` attacks (diagonally forward).
+pub fn pawn_attacks(color: usize, square: usize) -> Bitboard {
+  TABLES.pawn[color][square]
+}
+
+/// Squares a rook on `square` attacks, stopping at the `occupied` ones.
+pub fn rook_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+  let tables = &*TABLES;
+
+  tables.sliders[tables.rook[square].index(occupied)]
+}
+
+/// Squares a bishop on `square` attacks, stopping at the `occupied` ones.
+pub fn bishop_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+  let tables = &*TABLES;
+
+  tables.sliders[tables.bishop[square].index(occupied)]
+}
+
+/// Squares a queen on `square` attacks, stopping at the `occupied` ones.
+pub fn queen_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+  rook_attacks(square, occupied) | bishop_attacks(square, occupied)
+}
+
+/// Castling rights that survive a piece leaving or arriving on a square:
+/// moving the king or a rook, or taking a rook, loses them.
+pub fn castling_kept(square: usize) -> u8 {
+  match square {
+    0 => !WHITE_QUEEN_SIDE,
+    4 => !(WHITE_KING_SIDE | WHITE_QUEEN_SIDE),
+    7 => !WHITE_KING_SIDE,
+    56 => !BLACK_QUEEN_SIDE,
+    60 => !(BLACK_KING_SIDE | BLACK_QUEEN_SIDE),
+    63 => !BLACK_KING_SIDE,
+    _ => !0,
+  }
+}
+
+/// What a move does besides taking a piece from one square to another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flag {
+  Quiet,
+  Capture,
+  EnPassant,
+  Castling,
+}
+
+/// A move on bitboards.
+///
+/// Parameters:
+/// `from`, `to`: usize, squares
+/// `promotion`: Option<usize>, the piece type a pawn becomes
+/// `flag`: Flag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitMove {
+  pub from: usize,
+  pub to: usize,
+  pub promotion: Option<usize>,
+  pub flag: Flag,
+}
+
+/// A position: where the pieces are, whose turn it is, who can castle,
+/// en passant and the move counters. It's small and copied freely,
+/// e.g. to try a move out.
+///
+/// Parameters:
+/// `pieces`: [Bitboard; 6], by piece type, both colors
+/// `colors`: [Bitboard; 2], by color, every piece type
+/// `turn`: usize, the side to move
+/// `castling`: u8, see WHITE_KING_SIDE
+/// `en_passant`: Option<usize>, the square a pawn skipped on the last move
+/// `halfmove_clock`: u32, moves since the last capture or pawn move
+/// `fullmove_number`: u32, starts at 1 and goes up after every black move
+/// `hash`: u64, Zobrist key of everything but en passant, kept up to date by every change
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+  pieces: [Bitboard; 6],
+  colors: [Bitboard; 2],
+  turn: usize,
+  castling: u8,
+  en_passant: Option<usize>,
+  halfmove_clock: u32,
+  fullmove_number: u32,
+  hash: u64,
+}
+
+impl Position {
+  /// No pieces, white to move.
+  pub fn empty() -> Position {
+    Position{
+      pieces: [0; 6],
+      colors: [0; 2],
+      turn: WHITE,
+      castling: 0,
+      en_passant: None,
+      halfmove_clock: 0,
+      fullmove_number: 1,
+      hash: zobrist::turn(),
+    }
+  }
+
+  /// The starting position.
+  pub fn starting() -> Position {
+    let mut position = Self::empty();
+    let back_rank = [ROOK, KNIGHT, BISHOP, QUEEN, KING, BISHOP, KNIGHT, ROOK];
+
+    for (file, &kind) in back_rank.iter().enumerate() {
+      position.put(square(file, 0), WHITE, kind);
+      position.put(square(file, 1), WHITE, PAWN);
+      position.put(square(file, 6), BLACK, PAWN);
+      position.put(square(file, 7), BLACK, kind);
+    }
+
+    position.set_castling(WHITE_KING_SIDE | WHITE_QUEEN_SIDE | BLACK_KING_SIDE | BLACK_QUEEN_SIDE);
+
+    position
+  }
+
+  /// The piece on a square.
+  ///
+  /// Return: Option<(usize, usize)> (its color and type, None if the square is empty)
+  pub fn piece_at(&self, square: usize) -> Option<(usize, usize)> {
+    let mask = bit(square);
+
+    let color = match (self.colors[WHITE] & mask != 0, self.colors[BLACK] & mask != 0) {
+      (true, _) => WHITE,
+      (_, true) => BLACK,
+      _ => return None,
+    };
+
+    (0..6).find(|&kind| self.pieces[kind] & mask != 0).map(|kind| (color, kind))
+  }
+
+  /// Put a piece on a square, replacing whatever was there.
+  pub fn put(&mut self, square: usize, color: usize, kind: usize) {
+    self.remove(square);
+
+    self.pieces[kind] |= bit(square);
+    self.colors[color] |= bit(square);
+    self.hash ^= zobrist::piece(color, kind, square);
+  }
+
+  /// Take the piece off a square.
+  ///
+  /// Return: Option<(usize, usize)> (its color and type, None if the square was empty)
+  pub fn remove(&mut self, square: usize) -> Option<(usize, usize)> {
+    let piece = self.piece_at(square);
+
+    if let Some((color, kind)) = piece {
+      self.pieces[kind] &= !bit(square);
+      self.colors[color] &= !bit(square);
+      self.hash ^= zobrist::piece(color, kind, square);
+    }
+
+    piece
+  }
+
+  /// Every piece of one type and color.
+  pub fn pieces(&self, color: usize, kind: usize) -> Bitboard {
+    self.pieces[kind] & self.colors[color]
+  }
+
+  /// Every piece of one type, both colors.
+  pub fn pieces_of_kind(&self, kind: usize) -> Bitboard {
+    self.pieces[kind]
+  }
+
+  /// Every piece of one color.
+  pub fn side(&self, color: usize) -> Bitboard {
+    self.colors[color]
+  }
+
+  /// Every piece on the board.
+  pub fn occupied(&self) -> Bitboard {
+    self.colors[WHITE] | self.colors[BLACK]
+  }
+
+  /// The side to move.
+  pub fn turn(&self) -> usize {
+    self.turn
+  }
+
+  pub fn set_turn(&mut self, color: usize) {
+    if color != self.turn {
+      self.hash ^= zobrist::turn();
+    }
+
+    self.turn = color;
+  }
+
+  /// Castling rights, see WHITE_KING_SIDE.
+  pub fn castling(&self) -> u8 {
+    self.castling
+  }
+
+  /// Set the castling rights; nothing checks the kings and rooks are there.
+  pub fn set_castling(&mut self, castling: u8) {
+    if castling != self.castling {
+      self.hash ^= zobrist::castling(self.castling) ^ zobrist::castling(castling);
+      self.castling = castling;
+    }
+  }
+
+  /// The square a pawn skipped on the last move.
+  pub fn en_passant(&self) -> Option<usize> {
+    self.en_passant
+  }
+
+  pub fn set_en_passant(&mut self, square: Option<usize>) {
+    self.en_passant = square;
+  }
+
+  /// Half-moves since the last capture or pawn move.
+  pub fn halfmove_clock(&self) -> u32 {
+    self.halfmove_clock
+  }
+
+  /// Number of the current move, incremented after black moves.
+  pub fn fullmove_number(&self) -> u32 {
+    self.fullmove_number
+  }
+
+  pub fn set_move_counters(&mut self, halfmove_clock: u32, fullmove_number: u32) {
+    self.halfmove_clock = halfmove_clock;
+    self.fullmove_number = fullmove_number;
+  }
+
+  /// Zobrist key of the position: the pieces, the side to move, castling rights
+  /// and en passant. Like Polyglot, en passant only counts if a pawn of the side
+  /// to move stands next to the pawn that skipped (whether taking it is legal or not).
+  pub fn hash(&self) -> u64 {
+    match self.en_passant {
+      Some(target) if pawn_attacks(self.turn ^ 1, target) & self.pieces(self.turn, PAWN) != 0 => {
+        self.hash ^ zobrist::en_passant(target % 8)
+      },
+
+      _ => self.hash,
+    }
+  }
+
+  /// Where the king of a color is.
+  ///
+  /// Return: Option<usize> (None if it has no king)
+  pub fn king(&self, color: usize) -> Option<usize> {
+    squares(self.pieces(color, KING)).next()
+  }
+
+  /// Check if any piece of color `by` attacks the square.
+  pub fn is_attacked(&self, square: usize, by: usize) -> bool {
+    let occupied = self.occupied();
+    let diagonal = self.pieces(by, BISHOP) | self.pieces(by, QUEEN);
+    let straight = self.pieces(by, ROOK) | self.pieces(by, QUEEN);
+
+    // A pawn of `by` attacks the square if a pawn of the other color
+    // on the square would attack it
+    pawn_attacks(by ^ 1, square) & self.pieces(by, PAWN) != 0 ||
+      knight_attacks(square) & self.pieces(by, KNIGHT) != 0 ||
+      king_attacks(square) & self.pieces(by, KING) != 0 ||
+      bishop_attacks(square, occupied) & diagonal != 0 ||
+      rook_attacks(square, occupied) & straight != 0
+  }
+
+  /// Check if the king of a color is attacked. No king, no check.
+  pub fn is_in_check(&self, color: usize) -> bool {
+    match self.king(color) {
+      Some(king) => self.is_attacked(king, color ^ 1),
+      None => false,
+    }
+  }
+
+  /// Moves for a color that follow how the pieces move, but may leave the
+  /// own king in check. Castling out of and through check is left out already.
+  ///
+  /// Parameters:
+  /// `us`: usize, the color to move, not necessarily the side to move
+  /// `moves`: &mut Vec<BitMove>, where the moves go
+  pub fn pseudo_moves(&self, us: usize, moves: &mut Vec<BitMove>) {
+    let them = us ^ 1;
+    let own = self.colors[us];
+    let theirs = self.colors[them];
+    let occupied = own | theirs;
+
+    // Pawns walk up for white, down for black
+    let (start_rank, last_rank) = match us {
+      WHITE => (1, 7),
+      _ => (6, 0),
+    };
+
+    for from in squares(self.pieces(us, PAWN)) {
+      let forward = match us {
+        WHITE => from + 8,
+        _ => from.wrapping_sub(8),
+      };
+
+      // A pawn on the last rank can't go anywhere (set up by hand)
+      if forward >= 64 {
+        continue;
+      }
+
+      if occupied & bit(forward) == 0 {
+        Self::push_pawn(moves, from, forward, Flag::Quiet, last_rank);
+
+        let double = match us {
+          WHITE => from + 16,
+          _ => from.wrapping_sub(16),
+        };
+
+        if from / 8 == start_rank && occupied & bit(double) == 0 {
+          moves.push(BitMove{from, to: double, promotion: None, flag: Flag::Quiet});
+        }
+      }
+
+      for to in squares(pawn_attacks(us, from) & theirs) {
+        Self::push_pawn(moves, from, to, Flag::Capture, last_rank);
+      }
+
+      // The pawn that skipped the square stands next to us
+      if let Some(target) = self.en_passant {
+        let captured = square(target % 8, from / 8);
+
+        if pawn_attacks(us, from) & bit(target) & !occupied != 0 && self.pieces(them, PAWN) & bit(captured) != 0 {
+          moves.push(BitMove{from, to: target, promotion: None, flag: Flag::EnPassant});
+        }
+      }
+    }
+
+    for &kind in [KNIGHT, BISHOP, ROOK, QUEEN, KING].iter() {
+      for from in squares(self.pieces(us, kind)) {
+        let attacks = match kind {
+          KNIGHT => knight_attacks(from),
+          BISHOP => bishop_attacks(from, occupied),
+          ROOK => rook_attacks(from, occupied),
+          QUEEN => queen_attacks(from, occupied),
+          _ => king_attacks(from),
+        };
+
+        for to in squares(attacks & !own) {
+          let flag = match theirs & bit(to) != 0 {
+            true => Flag::Capture,
+            false => Flag::Quiet,
+          };
+
+          moves.push(BitMove{from, to, promotion: None, flag});
+        }
+      }
+    }
+
+    self.push_castling(moves, us);
+  }
+
+  /// A pawn move, or one for every piece it can become on the last rank.
+  fn push_pawn(moves: &mut Vec<BitMove>, from: usize, to: usize, flag: Flag, last_rank: usize) {
+    match to / 8 == last_rank {
+      true => for &kind in PROMOTIONS.iter() {
+        moves.push(BitMove{from, to, promotion: Some(kind), flag});
+      },
+      false => moves.push(BitMove{from, to, promotion: None, flag}),
+    };
+  }
+
+  /// Castling: the king and rook haven't moved (the rights say so), nothing
+  /// is between them, and the king isn't in check and doesn't cross an
+  /// attacked square. Castling into check is caught like any other move.
+  fn push_castling(&self, moves: &mut Vec<BitMove>, us: usize) {
+    let them = us ^ 1;
+
+    let (king_side, queen_side, home) = match us {
+      WHITE => (WHITE_KING_SIDE, WHITE_QUEEN_SIDE, 4),
+      _ => (BLACK_KING_SIDE, BLACK_QUEEN_SIDE, 60),
+    };
+
+    if self.castling & (king_side | queen_side) == 0 || self.pieces(us, KING) & bit(home) == 0 {
+      return;
+    }
+
+    let occupied = self.occupied();
+    let rooks = self.pieces(us, ROOK);
+
+    if self.is_attacked(home, them) {
+      return;
+    }
+
+    if self.castling & king_side != 0 && rooks & bit(home + 3) != 0 &&
+      occupied & (bit(home + 1) | bit(home + 2)) == 0 && !self.is_attacked(home + 1, them) {
+      moves.push(BitMove{from: home, to: home + 2, promotion: None, flag: Flag::Castling});
+    }
+
+    if self.castling & queen_side != 0 && rooks & bit(home - 4) != 0 &&
+      occupied & (bit(home - 1) | bit(home - 2) | bit(home - 3)) == 0 && !self.is_attacked(home - 1, them) {
+      moves.push(BitMove{from: home, to: home - 2, promotion: None, flag: Flag::Castling});
+    }
+  }
+
+  /// Check the move doesn't leave the mover's king in check.
+  pub fn is_legal(&self, m: &BitMove) -> bool {
+    let mover = match self.piece_at(m.from) {
+      Some((color, _)) => color,
+      None => return false,
+    };
+
+    let mut after = *self;
+    after.make(m);
+
+    !after.is_in_check(mover)
+  }
+
+  /// Legal moves for a color.
+  pub fn legal_moves_for(&self, us: usize) -> Vec<BitMove> {
+    let mut moves = Vec::with_capacity(64);
+    self.pseudo_moves(us, &mut moves);
+
+    moves.retain(|m| self.is_legal(m));
+
+    moves
+  }
+
+  /// Legal moves for the side to move.
+  pub fn legal_moves(&self) -> Vec<BitMove> {
+    self.legal_moves_for(self.turn)
+  }
+
+  /// Check a color can make at least one legal move.
+  pub fn has_legal_moves_for(&self, us: usize) -> bool {
+    let mut moves = Vec::with_capacity(64);
+    self.pseudo_moves(us, &mut moves);
+
+    moves.iter().any(|m| self.is_legal(m))
+  }
+
+  /// Make a move without checking it, and pass the turn to the other side.
+  /// The rook comes along when the king moves two squares, and moving the
+  /// king or a rook, or taking a rook, loses the castling rights.
+  pub fn make(&mut self, m: &BitMove) {
+    let (us, kind) = match self.piece_at(m.from) {
+      Some(piece) => piece,
+      None => return,
+    };
+
+    let captured = self.remove(m.to).is_some();
+
+    self.remove(m.from);
+    self.put(m.to, us, m.promotion.unwrap_or(kind));
+
+    // The pawn taken en passant is next to us, not on the destination
+    if m.flag == Flag::EnPassant {
+      self.remove(square(m.to % 8, m.from / 8));
+    }
+
+    // Castling, the rook jumps over the king
+    if kind == KING && (m.from % 8).max(m.to % 8) - (m.from % 8).min(m.to % 8) == 2 {
+      let (rook_from, rook_to) = match m.to > m.from {
+        true => (m.from + 3, m.from + 1),
+        false => (m.from - 4, m.from - 1),
+      };
+
+      if let Some((color, rook)) = self.remove(rook_from) {
+        self.put(rook_to, color, rook);
+      }
+    }
+
+    let castling = self.castling & castling_kept(m.from) & castling_kept(m.to);
+    self.set_castling(castling);
+
+    // A double step can be taken en passant on the next move only
+    self.en_passant = match kind == PAWN && (m.from / 8).max(m.to / 8) - (m.from / 8).min(m.to / 8) == 2 {
+      true => Some((m.from + m.to) / 2),
+      false => None,
+    };
+
+    self.halfmove_clock = match captured || kind == PAWN {
+      true => 0,
+      false => self.halfmove_clock + 1,
+    };
+
+    if us == BLACK {
+      self.fullmove_number += 1;
+    }
+
+    self.set_turn(us ^ 1);
+  }
+
+  /// Count the positions reachable in exactly `depth` moves.
+  pub fn perft(&self, depth: usize) -> u64 {
+    if depth == 0 {
+      return 1;
+    }
+
+    let mut moves = Vec::with_capacity(64);
+    self.pseudo_moves(self.turn, &mut moves);
+
+    let mut nodes = 0;
+
+    for m in moves.iter() {
+      let mut after = *self;
+      after.make(m);
+
+      if after.is_in_check(self.turn) {
+        continue;
+      }
+
+      nodes += match depth {
+        1 => 1,
+        _ => after.perft(depth - 1),
+      };
+    }
+
+    nodes
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  /// Look for a magic the way ROOK_MAGICS and BISHOP_MAGICS were found:
+  /// try sparse random numbers until one gives no collisions.
+  fn find_magic(square: usize, directions: &[(i32, i32)], seed: &mut u64) -> u64 {
+    let mask = relevant_squares(square, directions);
+    let bits = mask.count_ones();
+
+    let mut subsets = Vec::new();
+    let mut subset: Bitboard = 0;
+
+    loop {
+      subsets.push((subset, slide(square, subset, directions)));
+      subset = subset.wrapping_sub(mask) & mask;
+
+      if subset == 0 {
+        break;
+      }
+    }
+
+    // Xorshift, good enough for this
+    let mut random = || {
+      *seed ^= *seed >> 12;
+      *seed ^= *seed << 25;
+      *seed ^= *seed >> 27;
+      seed.wrapping_mul(2_685_821_657_736_338_717)
+    };
+
+    loop {
+      let magic = random() & random() & random();
+      let mut table = vec![None; 1 << bits];
+
+      let fits = subsets.iter().all(|&(subset, attacks)| {
+        let entry = &mut table[(subset.wrapping_mul(magic) >> (64 - bits)) as usize];
+
+        match *entry {
+          Some(other) => other == attacks,
+          None => {
+            *entry = Some(attacks);
+            true
+          },
+        }
+      });
+
+      if fits {
+        return magic;
+      }
+    }
+  }
+
+  #[test]
+  fn test_sliders() {
+    // Every way the pieces can be in the way, for every square
+    for square in 0..64 {
+      for &(directions, lookup) in [
+        (&ROOK_DIRECTIONS, rook_attacks as fn(usize, Bitboard) -> Bitboard),
+        (&BISHOP_DIRECTIONS, bishop_attacks as fn(usize, Bitboard) -> Bitboard),
+      ].iter() {
+        let mask = relevant_squares(square, directions);
+        let mut subset: Bitboard = 0;
+
+        loop {
+          // Pieces outside the mask (e.g. on the edges) don't matter
+          let occupied = subset | !mask & 0x8100_0000_0000_0081;
+
+          assert_eq!(lookup(square, occupied), slide(square, occupied, directions), "square {}", square);
+
+          subset = subset.wrapping_sub(mask) & mask;
+
+          if subset == 0 {
+            break;
+          }
+        }
+      }
+    }
+
+    // The finder finds magics that work
+    let mut seed = 0x9E37_79B9_7F4A_7C15;
+
+    for &square in [0, 27, 63].iter() {
+      let magic = find_magic(square, &ROOK_DIRECTIONS, &mut seed);
+      let mut table = Vec::new();
+      let entry = Magic::new(square, magic, &ROOK_DIRECTIONS, &mut table);
+
+      assert_eq!(table.len(), 1 << (64 - entry.shift));
+    }
+  }
+
+  #[test]
+  fn test_leapers() {
+    let a1 = square(0, 0);
+    let e4 = square(4, 3);
+
+    assert_eq!(squares(knight_attacks(a1)).collect::<Vec<usize>>(), vec![square(2, 1), square(1, 2)]);
+    assert_eq!(knight_attacks(e4).count_ones(), 8);
+    assert_eq!(king_attacks(a1).count_ones(), 3);
+    assert_eq!(pawn_attacks(WHITE, e4), bit(square(3, 4)) | bit(square(5, 4)));
+    assert_eq!(pawn_attacks(BLACK, e4), bit(square(3, 2)) | bit(square(5, 2)));
+    assert_eq!(pawn_attacks(WHITE, square(0, 7)), 0);
+  }
+
+  #[test]
+  fn test_position() {
+    let mut position = Position::starting();
+
+    assert_eq!(position.piece_at(square(4, 0)), Some((WHITE, KING)));
+    assert_eq!(position.piece_at(square(3, 7)), Some((BLACK, QUEEN)));
+    assert_eq!(position.piece_at(square(4, 4)), None);
+    assert_eq!(position.legal_moves().len(), 20);
+    assert_eq!(position.perft(3), 8_902);
+
+    // 1. e4, en passant square and black to move
+    position.make(&BitMove{from: square(4, 1), to: square(4, 3), promotion: None, flag: Flag::Quiet});
+
+    assert_eq!(position.en_passant(), Some(square(4, 2)));
+    assert_eq!(position.turn(), BLACK);
+    assert!(!position.is_attacked(square(4, 4), WHITE));
+    assert!(position.is_attacked(square(3, 4), WHITE));
+  }
+
+  /// Zobrist key worked out from scratch, without en passant.
+  fn full_hash(position: &Position) -> u64 {
+    let mut hash = zobrist::castling(position.castling());
+
+    for color in 0..2 {
+      for kind in 0..6 {
+        hash ^= zobrist::pieces(color, kind, position.pieces(color, kind));
+      }
+    }
+
+    match position.turn() {
+      WHITE => hash ^ zobrist::turn(),
+      _ => hash,
+    }
+  }
+
+  /// Check the incremental key against the full one everywhere `depth` moves deep.
+  fn check_hash(position: &Position, depth: usize) {
+    assert_eq!(position.hash, full_hash(position));
+
+    if depth > 0 {
+      for m in position.legal_moves().iter() {
+        let mut after = *position;
+        after.make(m);
+
+        check_hash(&after, depth - 1);
+      }
+    }
+  }
+
+  #[test]
+  fn test_hash() {
+    // Captures, castling, en passant and promotions
+    check_hash(&Position::starting(), 3);
+
+    // Every rook can castle or be taken, a pawn is about to promote
+    let mut castles = Position::empty();
+    let pieces = [
+      (WHITE, KING, 4), (WHITE, ROOK, 0), (WHITE, ROOK, 7), (WHITE, QUEEN, 21), (WHITE, KNIGHT, 36), (WHITE, PAWN, 35),
+      (BLACK, KING, 60), (BLACK, ROOK, 56), (BLACK, ROOK, 63), (BLACK, PAWN, 9), (BLACK, PAWN, 25), (BLACK, KNIGHT, 41),
+    ];
+
+    for &(color, kind, square) in pieces.iter() {
+      castles.put(square, color, kind);
+    }
+
+    castles.set_castling(WHITE_KING_SIDE | WHITE_QUEEN_SIDE | BLACK_KING_SIDE | BLACK_QUEEN_SIDE);
+    check_hash(&castles, 3);
+
+    // The knights go out and come back, same position
+    let start = Position::starting();
+    let mut position = start;
+
+    for &(from, to) in [(6, 21), (62, 45), (21, 6), (45, 62)].iter() {
+      position.make(&BitMove{from, to, promotion: None, flag: Flag::Quiet});
+    }
+
+    assert_eq!(position.hash(), start.hash());
+    assert!(position.hash() != Position::empty().hash());
+
+    // 1. e4, nobody can take en passant so it doesn't count
+    let mut e4 = start;
+    e4.make(&BitMove{from: square(4, 1), to: square(4, 3), promotion: None, flag: Flag::Quiet});
+
+    let mut without = e4;
+    without.set_en_passant(None);
+
+    assert_eq!(e4.hash(), without.hash());
+
+    // A black pawn on d4 could, so it does
+    e4.put(square(3, 3), BLACK, PAWN);
+    without.put(square(3, 3), BLACK, PAWN);
+
+    assert_eq!(e4.hash() ^ without.hash(), zobrist::en_passant(4));
+  }
+}
@@ -5,15 +5,25 @@ use colored::*;
 // Display trait
 use std::fmt;
 
-// Ordering
-use std::cmp::Ordering;
+// Parsing from notation
+use std::str::FromStr;
+
+// Bitboards behind the board
+use bitboard::{self, BitMove, Flag, Position};
 
 // Serialization
 extern crate serde_json;
 
+// Errors
+use error::ChessError;
+
+/// Forsyth-Edwards Notation of the starting position.
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
 /// Piece type
-#[derive(Clone, PartialEq, Serialize, Deserialize)]
-enum Piece {
+/// Nil is used for empty cells that have no pieces.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PieceKind {
   Pawn,
   Rook,
   Knight,
@@ -23,11 +33,68 @@ enum Piece {
   Nil,
 }
 
+// Piece letter, e.g. N or n for a knight
+impl FromStr for PieceKind {
+  type Err = ChessError;
+
+  fn from_str(letter: &str) -> Result<Self, Self::Err> {
+    match letter.to_uppercase().as_str() {
+      "P" => Ok(PieceKind::Pawn),
+      "R" => Ok(PieceKind::Rook),
+      "N" => Ok(PieceKind::Knight),
+      "B" => Ok(PieceKind::Bishop),
+      "Q" => Ok(PieceKind::Queen),
+      "K" => Ok(PieceKind::King),
+      _ => Err(ChessError::BadNotation(format!("'{}' is not a piece, expected one of P, R, N, B, Q or K.", letter))),
+    }
+  }
+}
+
+/// A piece of a given color, as it stands on the board.
+///
+/// Arguments:
+///
+/// `kind`: PieceKind
+/// `color`: Color
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Piece {
+  pub kind: PieceKind,
+  pub color: Color,
+}
+
+// FEN letter, uppercase for white and lowercase for black
+impl fmt::Display for Piece {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let letter = self.kind.to_string();
+
+    match self.color {
+      Color::Black => write!(f, "{}", letter.to_lowercase()),
+      _ => write!(f, "{}", letter),
+    }
+  }
+}
+
+// FEN letter, e.g. K is the white king and k the black one
+impl FromStr for Piece {
+  type Err = ChessError;
+
+  fn from_str(letter: &str) -> Result<Self, Self::Err> {
+    let kind = letter.parse::<PieceKind>()?;
+
+    let color = match letter.chars().all(|c| c.is_ascii_uppercase()) {
+      true => Color::White,
+      false => Color::Black,
+    };
+
+    Ok(Piece{kind, color})
+  }
+}
+
 /// Holds information about a game piece.
 #[derive(Clone, Serialize, Deserialize)]
 struct GamePiece {
   has_moved: bool,
-  piece_type: Piece,
+  piece_type: PieceKind,
 }
 
 impl GamePiece {
@@ -35,18 +102,13 @@ impl GamePiece {
   ///
   /// Arguments:
   ///
-  /// `piece_type`: Piece (enum)
-  fn new(piece_type: Piece) -> Self {
+  /// `piece_type`: PieceKind (enum)
+  fn new(piece_type: PieceKind) -> Self {
     Self{
       has_moved: false,
       piece_type,
     }
   }
-
-  /// Mark the piece as moved
-  fn moved(&mut self) {
-    self.has_moved = true;
-  }
 }
 
 // Implementation of fmt::Display for GamePiece
@@ -62,19 +124,19 @@ impl fmt::Display for GamePiece {
 
 
 // Implementation of fmt::Display
-impl fmt::Display for Piece {
+impl fmt::Display for PieceKind {
 
   /// Standard fmt method
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 
     let _ = match *self {
-      Piece::Pawn => write!(f, "P"),
-      Piece::Rook => write!(f, "R"),
-      Piece::Knight => write!(f, "N"),
-      Piece::Bishop => write!(f, "B"),
-      Piece::Queen => write!(f, "Q"),
-      Piece::King => write!(f, "K"),
-      Piece::Nil => write!(f, " "),
+      PieceKind::Pawn => write!(f, "P"),
+      PieceKind::Rook => write!(f, "R"),
+      PieceKind::Knight => write!(f, "N"),
+      PieceKind::Bishop => write!(f, "B"),
+      PieceKind::Queen => write!(f, "Q"),
+      PieceKind::King => write!(f, "K"),
+      PieceKind::Nil => write!(f, " "),
     };
 
     Ok(())
@@ -83,13 +145,24 @@ impl fmt::Display for Piece {
 
 /// Holds the color of the piece (black or white)
 /// Nil is used for empty cells that have no pieces.
-#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum Color {
   Black,
   White,
   Nil,
 }
 
+impl Color {
+  /// The other side.
+  pub fn opposite(&self) -> Color {
+    match *self {
+      Color::White => Color::Black,
+      Color::Black => Color::White,
+      Color::Nil => Color::Nil,
+    }
+  }
+}
+
 impl fmt::Display for Color {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let color = match *self {
@@ -129,344 +202,1107 @@ impl fmt::Display for Cell {
   }
 }
 
+/// A square of the board, e.g. e4.
+///
+/// Files and ranks count from 0, so a1 is Square::new(0, 0) and h8 is Square::new(7, 7).
+/// Internally the board is stored rank 8 first, see Square::position().
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Square {
+  file: usize,
+  rank: usize,
+}
+
+impl Square {
+  /// Standard new method
+  ///
+  /// Arguments:
+  ///
+  /// `file`: usize, 0 (a) to 7 (h)
+  /// `rank`: usize, 0 (1st rank) to 7 (8th rank)
+  ///
+  /// Return: Option<Square> (None if off the board)
+  pub fn new(file: usize, rank: usize) -> Option<Square> {
+    match file < 8 && rank < 8 {
+      true => Some(Square{file, rank}),
+      false => None,
+    }
+  }
+
+  /// Square at a position of the board vector, as used by the GUI.
+  ///
+  /// Arguments:
+  ///
+  /// `position`: tuple (2) of coordinates, (0, 0) is a8
+  ///
+  /// Return: Option<Square> (None if off the board)
+  pub fn from_position(position: (usize, usize)) -> Option<Square> {
+    match position.1 < 8 {
+      true => Self::new(position.0, 7 - position.1),
+      false => None,
+    }
+  }
+
+  /// Position of the square in the board vector, (0, 0) is a8.
+  pub fn position(&self) -> (usize, usize) {
+    (self.file, 7 - self.rank)
+  }
+
+  /// The file (column), 0 for a.
+  pub fn file(&self) -> usize {
+    self.file
+  }
+
+  /// The rank (row), 0 for the 1st rank.
+  pub fn rank(&self) -> usize {
+    self.rank
+  }
+}
+
+// Lowercase notation, e.g. e4
+impl fmt::Display for Square {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}{}", (b'a' + self.file as u8) as char, self.rank + 1)
+  }
+}
+
+// Notation in either case, e.g. e4 or E4
+impl FromStr for Square {
+  type Err = ChessError;
+
+  fn from_str(notation: &str) -> Result<Self, Self::Err> {
+    let chars: Vec<char> = notation.to_lowercase().chars().collect();
+
+    match chars.as_slice() {
+      &[file @ 'a'..='h', rank @ '1'..='8'] => Ok(Square{
+        file: file as usize - 'a' as usize,
+        rank: rank as usize - '1' as usize,
+      }),
+      _ => Err(ChessError::BadNotation(format!("'{}' is not a square, expected a file and a rank like e4.", notation))),
+    }
+  }
+}
+
+/// What a move does besides taking a piece from one cell to another.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MoveKind {
+  Quiet,
+  Capture,
+  EnPassant,
+  Castling,
+}
+
+/// A move, as produced by Board::legal_moves() or parsed from coordinate notation.
+///
+/// Only the board knows what a move does, so parsed moves are Quiet until
+/// Board::play() works out the rest.
+///
+/// Arguments:
+///
+/// `from`: Square
+/// `to`: Square
+/// `promotion`: Option<PieceKind>, what a pawn reaching the last row becomes
+/// `kind`: MoveKind
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Move {
+  pub from: Square,
+  pub to: Square,
+  pub promotion: Option<PieceKind>,
+  pub kind: MoveKind,
+}
+
+impl Move {
+  /// Standard new method
+  ///
+  /// Arguments:
+  ///
+  /// `from`: Square
+  /// `to`: Square
+  /// `promotion`: Option<PieceKind>
+  pub fn new(from: Square, to: Square, promotion: Option<PieceKind>) -> Self {
+    Move{
+      from,
+      to,
+      promotion,
+      kind: MoveKind::Quiet,
+    }
+  }
+}
+
+// Coordinate notation, e.g. e2e4 or e7e8q
+impl fmt::Display for Move {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}{}", self.from, self.to)?;
+
+    if let Some(ref piece) = self.promotion {
+      write!(f, "{}", piece.to_string().to_lowercase())?;
+    }
+
+    Ok(())
+  }
+}
+
+// Coordinate notation in either case, e.g. e2e4 or E7E8Q
+impl FromStr for Move {
+  type Err = ChessError;
+
+  fn from_str(notation: &str) -> Result<Self, Self::Err> {
+    if !notation.is_ascii() || (notation.len() != 4 && notation.len() != 5) {
+      return Err(ChessError::BadNotation(format!("'{}' is not a move, expected coordinates like e2e4 or e7e8q.", notation)));
+    }
+
+    let from = notation[0..2].parse::<Square>()?;
+    let to = notation[2..4].parse::<Square>()?;
+
+    let promotion = match notation.len() {
+      5 => Some(Board::letter_to_piece(&notation[4..])?),
+      _ => None,
+    };
+
+    Ok(Move::new(from, to, promotion))
+  }
+}
+
 /// Chess board, main interface to the game
 /// All board actions should be taken through the public functions.
 ///
 /// Arguments:
 ///
-/// `board`: Vec<Vec<Cell>>
+/// `position`: Position, the pieces as bitboards, the side to move, castling rights,
+/// en passant and the move counters
+/// `my_color`: Color of the local player
+///
+/// It's saved the way it always was (a grid of cells, see BoardData),
+/// so old saves and network peers still understand it.
 ///
 /// Example:
 ///
 /// See implementation for Self::new()
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(from = "BoardData", into = "BoardData")]
 pub struct Board {
-  /// Multidimensional vector holding the board cells.
+  position: Position,
+  my_color: Color,
+}
+
+/// How a board is serialized: a grid of cells, each piece knowing whether
+/// it moved (only kings and rooks care, for castling).
+///
+/// Arguments:
+///
+/// `board`: Vec<Vec<Cell>>, by column then row (row 0 is rank 8)
+/// `my_color`: Color of the local player
+/// `en_passant`: Option<(usize, usize)>, the cell a pawn skipped on the last move
+/// `turn`: Color, the side to move
+/// `halfmove_clock`: u32, moves since the last capture or pawn move
+/// `fullmove_number`: u32, starts at 1 and goes up after every black move
+#[derive(Serialize, Deserialize)]
+struct BoardData {
   board: Vec<Vec<Cell>>,
   my_color: Color,
+
+  #[serde(default)]
+  en_passant: Option<(usize, usize)>,
+
+  #[serde(default = "Board::first_turn")]
+  turn: Color,
+
+  #[serde(default)]
+  halfmove_clock: u32,
+
+  #[serde(default = "Board::first_move")]
+  fullmove_number: u32,
+}
+
+impl From<BoardData> for Board {
+  fn from(data: BoardData) -> Board {
+    let mut board = Board::empty(data.my_color.clone());
+
+    for (x, column) in data.board.iter().enumerate().take(8) {
+      for (y, cell) in column.iter().enumerate().take(8) {
+        if let (Some(color), Some(kind)) = (Board::color_index(&cell.color), Board::kind_index(&cell.piece.piece_type)) {
+          board.position.put(Board::to_square((x, y)), color, kind);
+        }
+      }
+    }
+
+    // Castling rights: the king and the rook haven't moved
+    let unmoved = |x: usize, y: usize, kind: PieceKind, color: Color| {
+      match data.board.get(x).and_then(|column| column.get(y)) {
+        Some(cell) => cell.piece.piece_type == kind && !cell.piece.has_moved && cell.color == color,
+        None => false,
+      }
+    };
+
+    let mut castling = 0;
+
+    for &(right, y, rook_x, ref color) in CASTLING.iter() {
+      if unmoved(4, y, PieceKind::King, color.clone()) && unmoved(rook_x, y, PieceKind::Rook, color.clone()) {
+        castling |= right;
+      }
+    }
+
+    board.position.set_castling(castling);
+    board.position.set_en_passant(data.en_passant.map(Board::to_square));
+    board.position.set_turn(Board::color_index(&data.turn).unwrap_or(bitboard::WHITE));
+    board.position.set_move_counters(data.halfmove_clock, data.fullmove_number);
+
+    board
+  }
+}
+
+impl From<Board> for BoardData {
+  fn from(board: Board) -> BoardData {
+    BoardData{
+      board: (0..8).map(|x| (0..8).map(|y| board.cell((x, y))).collect()).collect(),
+      my_color: board.my_color.clone(),
+      en_passant: board.position.en_passant().map(Board::to_coord),
+      turn: board.get_turn(),
+      halfmove_clock: board.position.halfmove_clock(),
+      fullmove_number: board.position.fullmove_number(),
+    }
+  }
 }
 
+/// Castling rights with the row of the king and the column of the rook.
+const CASTLING: [(u8, usize, usize, Color); 4] = [
+  (bitboard::WHITE_KING_SIDE, 7, 7, Color::White),
+  (bitboard::WHITE_QUEEN_SIDE, 7, 0, Color::White),
+  (bitboard::BLACK_KING_SIDE, 0, 7, Color::Black),
+  (bitboard::BLACK_QUEEN_SIDE, 0, 0, Color::Black),
+];
+
 impl Board {
   /// Standard Self::new method
-  /// Return an empty chess board (no pieces placed anywhere).
+  /// Return a chess board with the pieces in the starting position.
   pub fn new(my_color: Color) -> Self {
-    // Create board
-    let mut board = Board{
-      board: vec![vec![Cell{
-        piece: GamePiece::new(Piece::Nil),
-        color: Color::Nil,
-      }; 8]; 8],
+    Board{
+      position: Position::starting(),
       my_color,
-    };
-
-    // Place pawns
-    for x in 0..8 {
-      board.board[x][6] = Cell{
-        piece: GamePiece::new(Piece::Pawn),
-        color: Color::White,
-      };
+    }
+  }
 
-      board.board[x][1] = Cell{
-        piece: GamePiece::new(Piece::Pawn),
-        color: Color::Black,
-      };
+  /// Return an empty chess board (no pieces placed anywhere).
+  fn empty(my_color: Color) -> Self {
+    Board{
+      position: Position::empty(),
+      my_color,
     }
+  }
 
-    // Place rooks
-    for x in &[7, 0] {
-      board.board[*x][7] = Cell{
-        piece: GamePiece::new(Piece::Rook),
-        color: Color::White,
-      };
+  /// White moves first.
+  fn first_turn() -> Color {
+    Color::White
+  }
 
-      board.board[*x][0] = Cell{
-        piece: GamePiece::new(Piece::Rook),
-        color: Color::Black,
-      };
-    }
+  /// Moves are counted from 1.
+  fn first_move() -> u32 {
+    1
+  }
 
-    // Place knights
-    for x in &[6, 1] {
-      board.board[*x][7] = Cell{
-        piece: GamePiece::new(Piece::Knight),
-        color: Color::White,
-      };
+  /// Bitboard color of a Color (Nil is nobody's).
+  fn color_index(color: &Color) -> Option<usize> {
+    match *color {
+      Color::White => Some(bitboard::WHITE),
+      Color::Black => Some(bitboard::BLACK),
+      Color::Nil => None,
+    }
+  }
 
-      board.board[*x][0] = Cell{
-        piece: GamePiece::new(Piece::Knight),
-        color: Color::Black,
-      };
+  /// Color of a bitboard color.
+  fn index_color(color: usize) -> Color {
+    match color {
+      bitboard::WHITE => Color::White,
+      _ => Color::Black,
     }
+  }
 
-    // Place bishops
-    for x in &[5, 2] {
-      board.board[*x][7] = Cell{
-        piece: GamePiece::new(Piece::Bishop),
-        color: Color::White,
-      };
+  /// Bitboard piece type of a PieceKind (Nil is no piece).
+  fn kind_index(kind: &PieceKind) -> Option<usize> {
+    match *kind {
+      PieceKind::Pawn => Some(bitboard::PAWN),
+      PieceKind::Knight => Some(bitboard::KNIGHT),
+      PieceKind::Bishop => Some(bitboard::BISHOP),
+      PieceKind::Rook => Some(bitboard::ROOK),
+      PieceKind::Queen => Some(bitboard::QUEEN),
+      PieceKind::King => Some(bitboard::KING),
+      PieceKind::Nil => None,
+    }
+  }
 
-      board.board[*x][0] = Cell{
-        piece: GamePiece::new(Piece::Bishop),
-        color: Color::Black,
-      };
+  /// PieceKind of a bitboard piece type.
+  fn index_kind(kind: usize) -> PieceKind {
+    match kind {
+      bitboard::PAWN => PieceKind::Pawn,
+      bitboard::KNIGHT => PieceKind::Knight,
+      bitboard::BISHOP => PieceKind::Bishop,
+      bitboard::ROOK => PieceKind::Rook,
+      bitboard::QUEEN => PieceKind::Queen,
+      _ => PieceKind::King,
     }
+  }
 
-    // Place queens
-    board.board[3][7] = Cell{
-      piece: GamePiece::new(Piece::Queen),
-      color: Color::White,
-    };
+  /// Bitboard square of board coordinates (row 0 is rank 8).
+  fn to_square(coord: (usize, usize)) -> usize {
+    bitboard::square(coord.0, 7 - coord.1)
+  }
 
-    board.board[3][0] = Cell{
-      piece: GamePiece::new(Piece::Queen),
-      color: Color::Black,
-    };
+  /// Board coordinates of a bitboard square.
+  fn to_coord(square: usize) -> (usize, usize) {
+    (square % 8, 7 - square / 8)
+  }
 
-    // Place kings
-    board.board[4][7] = Cell{
-      piece: GamePiece::new(Piece::King),
-      color: Color::White,
-    };
+  /// Bitboard square of a Square.
+  fn bit_square(square: Square) -> usize {
+    bitboard::square(square.file, square.rank)
+  }
 
-    board.board[4][0] = Cell{
-      piece: GamePiece::new(Piece::King),
-      color: Color::Black,
-    };
+  /// Square of a bitboard square.
+  fn from_bit_square(square: usize) -> Square {
+    Square{file: square % 8, rank: square / 8}
+  }
 
-    board
+  /// The cell at board coordinates. Kings and rooks that can't castle have moved.
+  fn cell(&self, coord: (usize, usize)) -> Cell {
+    let square = Self::to_square(coord);
+
+    match self.position.piece_at(square) {
+      Some((color, kind)) => {
+        let castles = bitboard::castling_kept(square) & self.position.castling() != self.position.castling();
+
+        Cell{
+          piece: GamePiece{
+            has_moved: (kind == bitboard::KING || kind == bitboard::ROOK) && !castles,
+            piece_type: Self::index_kind(kind),
+          },
+          color: Self::index_color(color),
+        }
+      },
+
+      None => Cell{
+        piece: GamePiece::new(PieceKind::Nil),
+        color: Color::Nil,
+      },
+    }
   }
 
   /// Move piece from one cell to another on the board.
   ///
   /// Arguments:
   ///
-  /// `from`: tuple (2) of coordinates
-  /// `to`: tuple (2) of coordinates
+  /// `m`: &Move, only the squares and the promotion are looked at
+  /// `ignore_ownership`: bool, allow moving the other player's pieces
   ///
-  /// Return: std::Result<(), 'static str>
-  fn move_piece(&mut self, from: (usize, usize), to: (usize, usize), ignore_ownership: bool) -> Result<(), &'static str> {
-    // Expand tuples
-    let (f_x, f_y) = from;
-    let (t_x, t_y) = to;
-
-    println!("{} {}", self.board[f_x][f_y], self.my_color);
+  /// Return: Result<(), ChessError>
+  fn move_piece(&mut self, m: &Move, ignore_ownership: bool) -> Result<(), ChessError> {
+    let from = Self::bit_square(m.from);
+    let to = Self::bit_square(m.to);
 
     // Check if piece exists
-    if self.board[f_x][f_y].piece.piece_type == Piece::Nil {
-      return Err("This board cell is empty.");
+    let color = match self.position.piece_at(from) {
+      Some((color, _)) => color,
+      None => return Err(ChessError::EmptySquare(m.from)),
+    };
+
+    if !ignore_ownership && Self::index_color(color) != self.my_color {
+      return Err(ChessError::WrongOwner(m.from));
     }
 
-    else if !ignore_ownership && self.board[f_x][f_y].color != self.my_color {
-      return Err("You can only move your own pieces.");
+    // Check if it's this side's turn
+    else if color != self.position.turn() {
+      return Err(ChessError::NotYourTurn);
     }
 
+    let mut moves = Vec::new();
+    self.position.pseudo_moves(color, &mut moves);
+
     // Check if move is valid
-    else if !self.validate_move(from, to, &self.board[f_x][f_y].piece) {
-      return Err("Illegal move.");
+    let found = match moves.into_iter().find(|bm| bm.from == from && bm.to == to) {
+      Some(found) => found,
+      None => return Err(ChessError::IllegalMove(m.clone())),
+    };
+
+    // Check if the move exposes our own king
+    if !self.position.is_legal(&found) {
+      return Err(ChessError::KingInCheck(m.clone()));
     }
 
-    // // Check if destination cell is taken
-    // else if self.board[t_x][t_y].piece.piece_type != Piece::Nil {
-    //   return Err("The desintation cell is not empty.");
-    // }
+    let promotion = match (found.promotion, m.promotion.as_ref()) {
+      // Pawns reaching the last row have to become something else
+      (Some(_), None) => return Err(ChessError::MissingPromotion(m.clone())),
+
+      (Some(_), Some(kind)) => match *kind {
+        PieceKind::Knight | PieceKind::Bishop | PieceKind::Rook | PieceKind::Queen => Self::kind_index(kind),
+        _ => return Err(ChessError::IllegalMove(m.clone())),
+      },
+
+      // Only pawns reaching the last row can be promoted
+      (None, Some(_)) => return Err(ChessError::IllegalMove(m.clone())),
+
+      (None, None) => None,
+    };
 
     // Legal move
-    else {
-      self.board[t_x][t_y] = self.board[f_x][f_y].clone();
+    self.position.make(&BitMove{promotion, ..found});
+
+    Ok(())
+  }
 
-      // Mark the piece as moved at least once
-      self.board[t_x][t_y].piece.moved();
+  /// Check if the move takes a pawn to the last row.
+  ///
+  /// Arguments:
+  ///
+  /// `from`: tuple (2) of coordinates
+  /// `to`: tuple (2) of coordinates
+  ///
+  /// Return: bool (true if the pawn gets promoted, else false)
+  pub fn is_promotion(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+    self.position.pieces_of_kind(bitboard::PAWN) & bitboard::bit(Self::to_square(from)) != 0 && (to.1 == 0 || to.1 == 7)
+  }
 
-      // Empty the from cell
-      self.board[f_x][f_y] = Cell{
-        piece: GamePiece::new(Piece::Nil),
-        color: Color::Nil,
-      };
+  /// Translate castling notation (O-O or O-O-O) into the king's move.
+  ///
+  /// Parameters:
+  /// `notation`: &str (O-O for king side, O-O-O for queen side; zeros work too)
+  /// `color`: &Color, the side that is castling
+  ///
+  /// Return: Option<(&str, &str)> (None if this isn't castling notation).
+  pub fn castling_to_notation(notation: &str, color: &Color) -> Option<(&'static str, &'static str)> {
+    let notation = notation.to_uppercase().replace("0", "O");
+
+    match (notation.as_str(), color) {
+      ("O-O", &Color::White) => Some(("E1", "G1")),
+      ("O-O-O", &Color::White) => Some(("E1", "C1")),
+      ("O-O", &Color::Black) => Some(("E8", "G8")),
+      ("O-O-O", &Color::Black) => Some(("E8", "C8")),
+      _ => None,
     }
+  }
 
-    Ok(())
+  /// Convert a promotion letter to the piece the pawn becomes
+  ///
+  /// Parameters:
+  /// `letter`: &str (Q, R, B or N)
+  ///
+  /// Return: Result<PieceKind, ChessError>
+  fn letter_to_piece(letter: &str) -> Result<PieceKind, ChessError> {
+    match letter.parse::<PieceKind>() {
+      Ok(PieceKind::Pawn) | Ok(PieceKind::King) | Err(_) => {
+        Err(ChessError::BadNotation(format!("'{}' is not a promotion, pawns can only become a queen (Q), rook (R), bishop (B) or knight (N).", letter)))
+      },
+      Ok(piece_type) => Ok(piece_type),
+    }
   }
 
-  /// Calculate the discrete absolute derivative ( |d(to, from)| )
-  fn d(from: (usize, usize), to: (usize, usize)) -> (usize, usize) {
-    let dx = match from.0.cmp(&to.0) {
-      Ordering::Less => to.0 - from.0,
-      Ordering::Greater => from.0 - to.0,
-      Ordering::Equal => 0,
-    };
+  /// Make a move.
+  ///
+  /// Parameters:
+  /// `from`: &str (e.g. E6)
+  /// `to`: &str (e.g. B6)
+  pub fn make_move(&mut self, from: &str, to: &str, ignore_ownership: bool) -> Result<(), ChessError> {
+    self.make_move_with_promotion(from, to, None, ignore_ownership)
+  }
 
-    let dy = match from.1.cmp(&to.1) {
-      Ordering::Less => to.1 - from.1,
-      Ordering::Greater => from.1 - to.1,
-      Ordering::Equal => 0,
+  /// Make a move that may promote a pawn.
+  ///
+  /// Parameters:
+  /// `from`: &str (e.g. E7)
+  /// `to`: &str (e.g. E8)
+  /// `promotion`: Option<&str> (Q, R, B or N)
+  pub fn make_move_with_promotion(&mut self, from: &str, to: &str, promotion: Option<&str>, ignore_ownership: bool) -> Result<(), ChessError> {
+    let promotion = match promotion {
+      Some(letter) => Some(Self::letter_to_piece(letter)?),
+      None => None,
     };
 
-    (dx, dy)
+    let from = from.parse::<Square>()?;
+    let to = to.parse::<Square>()?;
+
+    self.play(&Move::new(from, to, promotion), ignore_ownership)
   }
 
-  /// Convert a letter column notation to an offset
+  /// Make a typed move. Only the squares and promotion are used,
+  /// the board works out what kind of move it is.
   ///
   /// Parameters:
-  /// `letter`: &str
+  /// `m`: &Move
+  /// `ignore_ownership`: bool, allow moving the other player's pieces
   ///
-  /// Return: Option<usize>
-  fn letter_to_column(letter: &str) -> usize {
-    assert_eq!(letter.len(), 1);
+  /// Return: Result<(), ChessError>
+  pub fn play(&mut self, m: &Move, ignore_ownership: bool) -> Result<(), ChessError> {
+    self.move_piece(m, ignore_ownership)
+  }
 
-    lazy_static! {
-      static ref ALPHABET: [&'static str; 8] = ["A", "B", "C", "D", "E", "F", "G", "H"];
-    }
+  /// Uppercase notation of a board position, e.g. (0, 0) is A8.
+  ///
+  /// Parameters:
+  /// `position`: tuple(2) of usize
+  ///
+  /// Return: Option<String> (None if the position is off the board)
+  pub fn position_to_notation(position: (usize, usize)) -> Option<String> {
+    Square::from_position(position).map(|square| square.to_string().to_uppercase())
+  }
 
-    match ALPHABET.iter().position(|&x| x == letter) {
-      Some(x) => x,
-      None => panic!("letter_to_column: Unknown column given."),
+  /// Check if any piece of the given color attacks the cell.
+  ///
+  /// Parameters:
+  /// `coord`: tuple(2) of usize
+  /// `by`: &Color, the attacking side
+  ///
+  /// Return: bool (true if attacked, else false).
+  pub fn is_attacked(&self, coord: (usize, usize), by: &Color) -> bool {
+    match Self::color_index(by) {
+      Some(by) => self.position.is_attacked(Self::to_square(coord), by),
+      None => false,
     }
   }
 
+  /// Check if the king of the given color is attacked.
   ///
-  fn column_to_letter(column: usize) -> &'static str {
-    assert!(column < 8);
-
-    lazy_static! {
-      static ref ALPHABET: [&'static str; 8] = ["A", "B", "C", "D", "E", "F", "G", "H"];
+  /// Parameters:
+  /// `color`: &Color
+  ///
+  /// Return: bool (true if in check, else false).
+  pub fn is_in_check(&self, color: &Color) -> bool {
+    match Self::color_index(color) {
+      Some(color) => self.position.is_in_check(color),
+      None => false,
     }
-
-    ALPHABET[column]
   }
 
-  /// Convert a number row to an offset
+  /// Check if the given color can make at least one legal move.
+  /// No moves and in check is checkmate; no moves and not in check is stalemate.
   ///
   /// Parameters:
-  /// `number`: &str
+  /// `color`: &Color
   ///
-  /// Return: Option<usize>
-  fn number_to_row(number: &str) -> usize {
-    assert_eq!(number.len(), 1);
+  /// Return: bool (true if a legal move exists, else false).
+  pub fn has_legal_moves(&self, color: &Color) -> bool {
+    match Self::color_index(color) {
+      Some(color) => self.position.has_legal_moves_for(color),
+      None => false,
+    }
+  }
 
-    let number = number.parse::<u8>().unwrap();
+  /// A bitboard move as a Move.
+  fn to_move(m: &BitMove) -> Move {
+    let kind = match m.flag {
+      Flag::Quiet => MoveKind::Quiet,
+      Flag::Capture => MoveKind::Capture,
+      Flag::EnPassant => MoveKind::EnPassant,
+      Flag::Castling => MoveKind::Castling,
+    };
 
-    (8 - number) as usize
+    Move{
+      from: Self::from_bit_square(m.from),
+      to: Self::from_bit_square(m.to),
+      promotion: m.promotion.map(Self::index_kind),
+      kind,
+    }
   }
 
+  /// Sort moves the way they were always listed: by the cell they come from,
+  /// then the cell they go to, column first and from the 8th row down.
+  /// Promotions keep their order (queen, rook, bishop, knight).
+  fn sort_moves<T, F: Fn(&T) -> &Move>(moves: &mut [T], get: F) {
+    moves.sort_by_key(|item| {
+      let m = get(item);
 
+      (m.from.file, 7 - m.from.rank, m.to.file, 7 - m.to.rank)
+    });
+  }
+
+  /// All legal moves for the side to move, including castling,
+  /// en passant and one move per promotion piece.
   ///
-  fn row_to_number(row: usize) -> String {
-    assert!(row < 8);
+  /// Return: Vec<Move>
+  pub fn legal_moves(&self) -> Vec<Move> {
+    let mut moves: Vec<Move> = self.position.legal_moves().iter().map(Self::to_move).collect();
 
-    (8 - row).to_string()
+    Self::sort_moves(&mut moves, |m| m);
+
+    moves
   }
 
-  /// Make a move.
+  /// Legal moves of the piece standing on the given square.
+  /// Empty if there is no piece or it's not its turn.
   ///
   /// Parameters:
-  /// `from`: &str (e.g. E6)
-  /// `to`: &str (e.g. B6)
-  pub fn make_move(&mut self, from: &str, to: &str, ignore_ownership: bool) -> Result<(), &'static str> {
-    assert_eq!(from.len(), 2);
-    assert_eq!(to.len(), 2);
+  /// `square`: Square
+  ///
+  /// Return: Vec<Move>
+  pub fn legal_moves_from(&self, square: Square) -> Vec<Move> {
+    let from = Self::bit_square(square);
 
-    let from = (
-      Self::letter_to_column(&from[..1]),
-      Self::number_to_row(&from[1..])
-    );
+    let mut moves: Vec<Move> = self.position.legal_moves().iter()
+      .filter(|m| m.from == from)
+      .map(Self::to_move)
+      .collect();
 
-    let to = (
-      Self::letter_to_column(&to[..1]),
-      Self::number_to_row(&to[1..])
-    );
+    Self::sort_moves(&mut moves, |m| m);
 
-    self.move_piece(from, to, ignore_ownership)
+    moves
   }
 
-
+  /// Count the positions reachable in exactly `depth` moves (performance test).
+  /// Compared against known numbers, it tells if move generation is right.
   ///
-  pub fn position_to_notation(position: (usize, usize)) -> String {
-    let letter = Self::column_to_letter(position.0);
-    let number = Self::row_to_number(position.1);
-
-    String::from(letter) + &number
+  /// Parameters:
+  /// `depth`: usize
+  ///
+  /// Return: u64 (number of leaf positions)
+  pub fn perft(&self, depth: usize) -> u64 {
+    self.position.perft(depth)
   }
 
-  /// Make sure a piece makes a legal move.
+  /// Perft split by the first move, handy for finding which move is miscounted.
   ///
   /// Parameters:
-  /// `from`: tuple(2) of usize
-  /// `to`: tuple(2) of usize
-  /// `piece`: &GamePiece
-  ///
-  /// Return: bool (true if valid, else false).
-  ///
-  /// TODO: Collision, e.g. bishops can't jump over pieces, but knights can.
-  fn validate_move(&self, from: (usize, usize), to: (usize, usize), piece: &GamePiece) -> bool {
-    
-    let (dx, dy) = Self::d(from, to);
-
-    match piece.piece_type {
-      Piece::Pawn => {
-        // The pawn has moved, it can only move one field forward.
-        // Or it can move one field diagonally if it captures another piece.           
-        if piece.has_moved {
-          return (dx == 0 && dy == 1 && !self.has_piece(to)) || (dx == 1 && dy == 1 && self.has_piece(to));        
-        }
-        // The pawn has not moved, so it can one field forward or two for an opening.                  
-        else {
-          return (dx == 0 && (dy == 1 || dy == 2) && !self.has_piece(to)) || (dx == 1 && dy == 1 && self.has_piece(to));
-        }
-      },
+  /// `depth`: usize (at least 1)
+  ///
+  /// Return: Vec<(Move, u64)>
+  pub fn divide(&self, depth: usize) -> Vec<(Move, u64)> {
+    let mut divide: Vec<(Move, u64)> = self.position.legal_moves().iter().map(|m| {
+      let mut position = self.position;
+      position.make(m);
 
-      Piece::Rook => {
-        // Can only move on rows and columns, but not both ;)
-        return (dx == 0 && dy != 0) || (dx != 0 && dy == 0);
-      },
+      (Self::to_move(m), position.perft(depth.saturating_sub(1)))
+    }).collect();
 
-      Piece::Knight => {
-        // The greek letter gamma (capital).
-        return (dx == 2 && dy == 1) || (dx == 1 && dy == 2);
-      },
+    Self::sort_moves(&mut divide, |(m, _)| m);
 
-      Piece::Bishop => {
-        return dx == dy && dx != 0; // Only on a diagonal,
-      },
+    divide
+  }
 
-      Piece::Queen => {
-        // Diagonally or on columns or rows.
-        return (dx == dy && dx != 0) || (dx == 0 && dy != 0) || (dx == 0 && dy != 0);
-      },
+  /// Check if piece exists at coordinate.
+  ///
+  /// Parameters:
+  /// `coord` tuple(2) of usize
+  ///
+  /// Return: bool (true if exists, else false)
+  pub fn has_piece(&self, coord: (usize, usize)) -> bool {
+    self.position.occupied() & bitboard::bit(Self::to_square(coord)) != 0
+  }
+
+
+  pub fn get_color(&self, coord: (usize, usize)) -> Color {
+    match self.position.piece_at(Self::to_square(coord)) {
+      Some((color, _)) => Self::index_color(color),
+      None => Color::Nil,
+    }
+  }
+
+  /// The piece standing on a square, if any.
+  ///
+  /// Parameters:
+  /// `square`: Square
+  ///
+  /// Return: Option<Piece>
+  pub fn piece_at(&self, square: Square) -> Option<Piece> {
+    self.position.piece_at(Self::bit_square(square)).map(|(color, kind)| Piece{
+      kind: Self::index_kind(kind),
+      color: Self::index_color(color),
+    })
+  }
+
+  /// The color of the local player.
+  pub fn get_my_color(&self) -> Color {
+    self.my_color.clone()
+  }
+
+  /// The color of the side to move.
+  pub fn get_turn(&self) -> Color {
+    Self::index_color(self.position.turn())
+  }
+
+  /// Half-moves since the last capture or pawn move.
+  pub fn get_halfmove_clock(&self) -> u32 {
+    self.position.halfmove_clock()
+  }
+
+  /// Zobrist key of the position, for repetitions, transposition tables and
+  /// opening books. Covers the pieces, the side to move, castling rights and
+  /// en passant, using the Polyglot layout. Like Polyglot, en passant counts
+  /// whenever a pawn stands next to the one that just moved, even if taking
+  /// would leave its king in check; repetitions follow the same rule.
+  ///
+  /// Return: u64
+  pub fn hash(&self) -> u64 {
+    self.position.hash()
+  }
+
+  /// Check if neither side has enough pieces left to checkmate: kings only,
+  /// a single knight or bishop, or bishops all standing on the same color.
+  ///
+  /// Return: bool (true if nobody can win, else false)
+  pub fn has_insufficient_material(&self) -> bool {
+    let pieces = |kind| self.position.pieces_of_kind(kind);
+
+    if pieces(bitboard::PAWN) | pieces(bitboard::ROOK) | pieces(bitboard::QUEEN) != 0 {
+      return false;
+    }
+
+    let bishops = pieces(bitboard::BISHOP);
+
+    // Kings alone, or with one minor piece
+    if (pieces(bitboard::KNIGHT) | bishops).count_ones() <= 1 {
+      return true;
+    }
+
+    // Only bishops, all on light or all on dark cells
+    pieces(bitboard::KNIGHT) == 0 && (bishops & bitboard::LIGHT_SQUARES == 0 || bishops & !bitboard::LIGHT_SQUARES == 0)
+  }
+
+  ///
+  pub fn serialize(&self) -> String {
+    serde_json::to_string(self).unwrap()
+  }
 
-      Piece::King => {
-        if piece.has_moved {
-          return dx == 1 || dy == 1;
+  /// Convert notation to a position on the board, without panicking on bad input.
+  ///
+  /// Parameters:
+  /// `notation`: &str (e.g. e4 or E4)
+  ///
+  /// Return: Option<(usize, usize)>
+  pub fn notation_to_position(notation: &str) -> Option<(usize, usize)> {
+    notation.parse::<Square>().ok().map(|square| square.position())
+  }
+
+  /// Set up a board from Forsyth-Edwards Notation, e.g.
+  /// rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1
+  ///
+  /// The move counters are optional and default to 0 and 1.
+  ///
+  /// Parameters:
+  /// `fen`: &str
+  /// `my_color`: Color of the local player
+  ///
+  /// Return: Result<Board, ChessError> (a parse error describing what is wrong with the FEN)
+  pub fn from_fen(fen: &str, my_color: Color) -> Result<Board, ChessError> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+
+    if fields.len() != 4 && fields.len() != 6 {
+      return Err(ChessError::Parse(format!("FEN needs 6 fields (or 4 without the move counters), found {}.", fields.len())));
+    }
+
+    let mut board = Self::empty(my_color);
+
+    // Piece placement, from the 8th rank down to the 1st
+    let ranks: Vec<&str> = fields[0].split('/').collect();
+
+    if ranks.len() != 8 {
+      return Err(ChessError::Parse(format!("FEN piece placement needs 8 ranks, found {}.", ranks.len())));
+    }
+
+    for (y, rank) in ranks.iter().enumerate() {
+      let mut x = 0;
+
+      for c in rank.chars() {
+        if let Some(empty) = c.to_digit(10) {
+          if empty < 1 || empty > 8 {
+            return Err(ChessError::Parse(format!("FEN rank {} skips {} files.", 8 - y, empty)));
+          }
+
+          x += empty as usize;
+          continue;
         }
 
-        else {
-           return (dx ==  1 || dy == 1) && (dx == 2 && dy == 0);  
+        if x >= 8 {
+          return Err(ChessError::Parse(format!("FEN rank {} has more than 8 files.", 8 - y)));
         }
-      },
 
-      Piece::Nil => {
-        return false; // Irrelevant.
+        let (color, kind) = match Self::fen_to_piece(c) {
+          Some(piece) => piece,
+          None => return Err(ChessError::Parse(format!("FEN rank {} has an unknown piece '{}'.", 8 - y, c))),
+        };
+
+        board.position.put(Self::to_square((x, y)), color, kind);
+
+        x += 1;
+      }
+
+      if x != 8 {
+        return Err(ChessError::Parse(format!("FEN rank {} has {} files instead of 8.", 8 - y, x)));
+      }
+    }
+
+    // Side to move
+    match fields[1] {
+      "w" => board.position.set_turn(bitboard::WHITE),
+      "b" => board.position.set_turn(bitboard::BLACK),
+      other => return Err(ChessError::Parse(format!("FEN side to move must be 'w' or 'b', found '{}'.", other))),
+    };
+
+    // Castling rights; the kings and rooks that can castle haven't moved
+    board.set_castling(fields[2]).map_err(|err| ChessError::Parse(format!("Bad FEN castling rights. {}", err)))?;
+
+    // En passant target, behind the pawn that just made a double step
+    if fields[3] != "-" {
+      let position = match Self::notation_to_position(fields[3]) {
+        Some(position) => position,
+        None => return Err(ChessError::Parse(format!("FEN en passant square '{}' is not a square.", fields[3]))),
+      };
+
+      let expected_row = match board.position.turn() {
+        bitboard::WHITE => 2,
+        _ => 5,
+      };
+
+      if position.1 != expected_row {
+        return Err(ChessError::Parse(format!("FEN en passant square '{}' is on the wrong rank.", fields[3])));
       }
+
+      board.position.set_en_passant(Some(Self::to_square(position)));
     }
+
+    // Move counters
+    if fields.len() == 6 {
+      let halfmove_clock = match fields[4].parse::<u32>() {
+        Ok(clock) => clock,
+        Err(_) => return Err(ChessError::Parse(format!("FEN halfmove clock must be a number, found '{}'.", fields[4]))),
+      };
+
+      let fullmove_number = match fields[5].parse::<u32>() {
+        Ok(number) if number > 0 => number,
+        _ => return Err(ChessError::Parse(format!("FEN fullmove number must be a positive number, found '{}'.", fields[5]))),
+      };
+
+      board.position.set_move_counters(halfmove_clock, fullmove_number);
+    }
+
+    Ok(board)
   }
 
-  /// Check if piece exists at coordinate.
+  /// Describe the board in Forsyth-Edwards Notation.
+  ///
+  /// Return: String
+  pub fn to_fen(&self) -> String {
+    let mut fen = String::new();
+
+    // Piece placement
+    for y in 0..8 {
+      let mut empty = 0;
+
+      for x in 0..8 {
+        match self.piece_at(Square{file: x, rank: 7 - y}) {
+          Some(piece) => {
+            if empty > 0 {
+              fen.push_str(&empty.to_string());
+              empty = 0;
+            }
+
+            fen.push_str(&piece.to_string());
+          },
+
+          None => empty += 1,
+        };
+      }
+
+      if empty > 0 {
+        fen.push_str(&empty.to_string());
+      }
+
+      if y < 7 {
+        fen.push('/');
+      }
+    }
+
+    // Side to move
+    fen.push_str(match self.position.turn() {
+      bitboard::BLACK => " b ",
+      _ => " w ",
+    });
+
+    // Castling rights
+    let mut castling = String::new();
+
+    for (&(right, _, _, _), letter) in CASTLING.iter().zip("KQkq".chars()) {
+      if self.position.castling() & right != 0 {
+        castling.push(letter);
+      }
+    }
+
+    if castling.is_empty() {
+      castling.push('-');
+    }
+
+    fen.push_str(&castling);
+
+    // En passant
+    let en_passant = match self.position.en_passant() {
+      Some(square) => Self::from_bit_square(square).to_string(),
+      None => String::from("-"),
+    };
+
+    fen.push_str(&format!(" {} {} {}", en_passant, self.position.halfmove_clock(), self.position.fullmove_number()));
+
+    fen
+  }
+
+  /// Take every piece off the board, e.g. to set up a position from scratch.
+  /// The side to move and the move counters stay as they are.
+  pub fn clear(&mut self) {
+    let mut position = Position::empty();
+
+    position.set_turn(self.position.turn());
+    position.set_move_counters(self.position.halfmove_clock(), self.position.fullmove_number());
+
+    self.position = position;
+  }
+
+  /// Put a piece on a square, replacing whatever was there.
+  /// Kings and rooks put on the board can't castle until set_castling() says so.
   ///
   /// Parameters:
-  /// `coord` tuple(2) of usize
+  /// `square`: Square
+  /// `piece`: Piece, a Nil piece (or color) empties the square
+  pub fn place(&mut self, square: Square, piece: Piece) {
+    let (color, kind) = match (Self::color_index(&piece.color), Self::kind_index(&piece.kind)) {
+      (Some(color), Some(kind)) => (color, kind),
+      _ => {
+        self.remove(square);
+        return;
+      },
+    };
+
+    let square = Self::bit_square(square);
+    let castling = self.position.castling() & bitboard::castling_kept(square);
+
+    self.position.put(square, color, kind);
+    self.position.set_castling(castling);
+
+    // The pawn that could be taken en passant may be gone
+    self.position.set_en_passant(None);
+  }
+
+  /// Take the piece off a square.
   ///
-  /// Return: bool (true if exists, else false)
-  pub fn has_piece(&self, coord: (usize, usize)) -> bool {
-    return self.board[coord.0][coord.1].piece.piece_type != Piece::Nil;
+  /// Parameters:
+  /// `square`: Square
+  ///
+  /// Return: Option<Piece> (the piece that was there, None if it was empty)
+  pub fn remove(&mut self, square: Square) -> Option<Piece> {
+    let piece = self.piece_at(square);
+    let square = Self::bit_square(square);
+    let castling = self.position.castling() & bitboard::castling_kept(square);
+
+    self.position.remove(square);
+    self.position.set_castling(castling);
+    self.position.set_en_passant(None);
+
+    piece
   }
 
+  /// Choose the side to move.
+  ///
+  /// Parameters:
+  /// `color`: Color, White or Black
+  pub fn set_turn(&mut self, color: Color) {
+    if let Some(color) = Self::color_index(&color) {
+      if color != self.position.turn() {
+        self.position.set_turn(color);
+        self.position.set_en_passant(None);
+      }
+    }
+  }
 
-  pub fn get_color(&self, coord: (usize, usize)) -> Color {
-    return self.board[coord.0][coord.1].color.clone();
+  /// Choose who can still castle, the way FEN writes it.
+  ///
+  /// Parameters:
+  /// `rights`: &str, a mix of KQkq, or - for nobody
+  ///
+  /// Return: Result<(), ChessError> (an illegal position error if a king or rook isn't where castling needs it;
+  /// the rights don't change then)
+  pub fn set_castling(&mut self, rights: &str) -> Result<(), ChessError> {
+    let mut castling = 0;
+
+    if rights != "-" {
+      for (i, right) in rights.char_indices() {
+        if rights[..i].contains(right) {
+          return Err(ChessError::IllegalPosition(format!("Castling right '{}' is repeated.", right)));
+        }
+
+        let &(bit, row, rook_column, ref color) = match "KQkq".find(right) {
+          Some(index) => &CASTLING[index],
+          None => return Err(ChessError::IllegalPosition(format!("Castling rights must be '-' or a mix of KQkq, found '{}'.", right))),
+        };
+
+        let king = self.cell((4, row));
+        let rook = self.cell((rook_column, row));
+
+        if king.piece.piece_type != PieceKind::King || king.color != *color || rook.piece.piece_type != PieceKind::Rook || rook.color != *color {
+          return Err(ChessError::IllegalPosition(format!(
+            "Castling right '{}' needs a king on {} and a rook on {}.",
+            right,
+            Square{file: 4, rank: 7 - row},
+            Square{file: rook_column, rank: 7 - row},
+          )));
+        }
+
+        castling |= bit;
+      }
+    }
+
+    self.position.set_castling(castling);
+
+    Ok(())
   }
 
+  /// Check a position set up by hand can be played: one king each,
+  /// no pawns on the first or last rank and the side that just moved
+  /// isn't in check.
   ///
-  pub fn serialize(&self) -> String {
-    serde_json::to_string(self).unwrap()
+  /// Return: Result<(), ChessError> (an illegal position error saying what is wrong)
+  pub fn validate(&self) -> Result<(), ChessError> {
+    for color in &[Color::White, Color::Black] {
+      let kings = match Self::color_index(color) {
+        Some(index) => self.position.pieces(index, bitboard::KING).count_ones(),
+        None => 0,
+      };
+
+      if kings != 1 {
+        return Err(ChessError::IllegalPosition(format!("{} needs exactly one king, found {}.", Self::color_name(color), kings)));
+      }
+    }
+
+    for &y in &[0, 7] {
+      for x in 0..8 {
+        if self.position.pieces_of_kind(bitboard::PAWN) & bitboard::bit(Self::to_square((x, y))) != 0 {
+          return Err(ChessError::IllegalPosition(format!(
+            "Pawns can't stand on the first or last rank, found one on {}.",
+            Square{file: x, rank: 7 - y},
+          )));
+        }
+      }
+    }
+
+    let turn = self.get_turn();
+    let waiting = turn.opposite();
+
+    if self.is_in_check(&waiting) {
+      return Err(ChessError::IllegalPosition(format!(
+        "{} is in check but it's {}'s turn.",
+        Self::color_name(&waiting),
+        Self::color_name(&turn),
+      )));
+    }
+
+    Ok(())
+  }
+
+  /// Color name for messages.
+  fn color_name(color: &Color) -> &'static str {
+    match *color {
+      Color::White => "White",
+      Color::Black => "Black",
+      Color::Nil => "Nobody",
+    }
+  }
+
+  /// FEN letter to a bitboard color and piece type (uppercase is white, lowercase is black).
+  fn fen_to_piece(c: char) -> Option<(usize, usize)> {
+    let piece = c.to_string().parse::<Piece>().ok()?;
+
+    Some((Self::color_index(&piece.color)?, Self::kind_index(&piece.kind)?))
   }
 
 }
@@ -475,13 +1311,23 @@ impl fmt::Display for Board {
 
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 
-    for y in 0..8 {
+    // Black sees the board from the other side: rank 1 on top, H on the left
+    let flipped = self.my_color == Color::Black;
+
+    let order = |i: usize| match flipped {
+      true => 7 - i,
+      false => i,
+    };
+
+    for row in 0..8 {
+      let y = order(row);
+
       // Row hint (numbers)
       write!(f, "{}", (8-y).to_string().blue())?;
-      
+
       // Piece
-      for x in 0..8 {
-        write!(f, " {} ", self.board[x][y])?
+      for column in 0..8 {
+        write!(f, " {} ", self.cell((order(column), y)))?
       }
 
       // Two new lines
@@ -491,12 +1337,12 @@ impl fmt::Display for Board {
     lazy_static! {
       static ref ALPHABET: [&'static str; 8] = ["A", "B", "C", "D", "E", "F", "G", "H"];
     }
-    
+
     write!(f, " ")?; // Little offset
 
     // Column hint (letters)
-    for x in ALPHABET.iter() {
-      write!(f, " {} ", x.blue())?;
+    for column in 0..8 {
+      write!(f, " {} ", ALPHABET[order(column)].blue())?;
     }
 
     Ok(())
@@ -507,10 +1353,741 @@ impl fmt::Display for Board {
 mod tests {
   use super::*;
 
+  /// A board with nothing on it.
+  fn empty_board() -> Board {
+    let mut board = Board::new(Color::White);
+    board.clear();
+
+    board
+  }
+
+  /// Put a piece on the board, e.g. place(&mut board, "E4", PieceKind::Rook, Color::White).
+  fn place(board: &mut Board, square: &str, piece_type: PieceKind, color: Color) {
+    board.place(square.parse().unwrap(), Piece{kind: piece_type, color});
+  }
+
+  /// The kind of piece on a square, Nil if it's empty.
+  fn kind_at(board: &Board, square: &str) -> PieceKind {
+    board.piece_at(square.parse().unwrap()).map_or(PieceKind::Nil, |piece| piece.kind)
+  }
+
   #[test]
   fn test_board_position_to_notation() {
     let position = (0, 0);
 
-    assert_eq!(String::from("A8"), Board::position_to_notation(position));
+    assert_eq!(Some(String::from("A8")), Board::position_to_notation(position));
+    assert_eq!(None, Board::position_to_notation((8, 0)));
+  }
+
+  #[test]
+  fn test_square() {
+    let square: Square = "e4".parse().unwrap();
+
+    assert_eq!(square, Square::new(4, 3).unwrap());
+    assert_eq!(square, "E4".parse().unwrap());
+    assert_eq!(square.to_string(), "e4");
+    assert_eq!(square.position(), (4, 4));
+    assert_eq!(Square::from_position((0, 0)).unwrap().to_string(), "a8");
+
+    assert!(Square::new(8, 0).is_none());
+    assert!(Square::from_position((0, 8)).is_none());
+
+    for bad in &["", "e", "e9", "i1", "e44", "é4"] {
+      assert!(bad.parse::<Square>().is_err(), "{}", bad);
+    }
+  }
+
+  #[test]
+  fn test_piece() {
+    assert_eq!("n".parse::<Piece>().unwrap(), Piece{kind: PieceKind::Knight, color: Color::Black});
+    assert_eq!("Q".parse::<Piece>().unwrap(), Piece{kind: PieceKind::Queen, color: Color::White});
+    assert_eq!(Piece{kind: PieceKind::King, color: Color::Black}.to_string(), "k");
+    assert!("x".parse::<Piece>().is_err());
+    assert!("".parse::<PieceKind>().is_err());
+
+    let board = Board::new(Color::White);
+
+    assert_eq!(board.piece_at("d1".parse().unwrap()), Some(Piece{kind: PieceKind::Queen, color: Color::White}));
+    assert_eq!(board.piece_at("e5".parse().unwrap()), None);
+  }
+
+  #[test]
+  fn test_typed_moves() {
+    let m: Move = "e7e8q".parse().unwrap();
+
+    assert_eq!(m.from, "e7".parse().unwrap());
+    assert_eq!(m.to, "e8".parse().unwrap());
+    assert_eq!(m.promotion, Some(PieceKind::Queen));
+    assert_eq!("E2E4".parse::<Move>().unwrap().to_string(), "e2e4");
+
+    for bad in &["e2", "e2e9", "e7e8k", "e2e4qq"] {
+      assert!(bad.parse::<Move>().is_err(), "{}", bad);
+    }
+
+    let mut board = Board::new(Color::White);
+
+    assert!(board.play(&"e2e4".parse().unwrap(), false).is_ok());
+    assert!(board.play(&"e4e5".parse().unwrap(), false).is_err()); // Black's turn
+    assert_eq!(board.piece_at("e4".parse().unwrap()), Some(Piece{kind: PieceKind::Pawn, color: Color::White}));
+
+    // Bad notation is an error, not a panic
+    assert!(board.make_move("E77", "E5", true).is_err());
+    assert!(board.make_move("Z7", "E5", true).is_err());
+  }
+
+  #[test]
+  fn test_pawn_collision() {
+    let mut board = Board::new(Color::White);
+
+    // Knight in front of the E pawn
+    place(&mut board, "E3", PieceKind::Knight, Color::Black);
+
+    assert!(board.make_move("E2", "E3", false).is_err());
+    assert!(board.make_move("E2", "E4", false).is_err());
+
+    // Captures go diagonally, and never on your own pieces
+    assert!(board.make_move("D2", "E3", false).is_ok());
+    assert!(board.make_move("F2", "G3", false).is_err());
+    assert!(board.make_move("C2", "D3", false).is_err());
+  }
+
+  #[test]
+  fn test_pawn_direction() {
+    let mut board = empty_board();
+
+    place(&mut board, "E4", PieceKind::Pawn, Color::White);
+    place(&mut board, "D3", PieceKind::Pawn, Color::Black);
+    place(&mut board, "D5", PieceKind::Pawn, Color::Black);
+    place(&mut board, "H5", PieceKind::Pawn, Color::Black);
+
+    // No walking or capturing backwards
+    assert!(board.make_move("E4", "E3", false).is_err());
+    assert!(board.make_move("E4", "D3", false).is_err());
+
+    // Not on the starting row, so no double step even if it never moved
+    assert!(board.make_move("E4", "E6", false).is_err());
+    assert!(board.make_move("E4", "D5", false).is_ok());
+
+    // Black goes the other way
+    assert!(board.make_move("D3", "D4", true).is_err());
+    assert!(board.make_move("H5", "H3", true).is_err());
+    assert!(board.make_move("D3", "D2", true).is_ok());
+  }
+
+  #[test]
+  fn test_rook_collision() {
+    let mut board = empty_board();
+
+    place(&mut board, "A1", PieceKind::Rook, Color::White);
+    place(&mut board, "A4", PieceKind::Pawn, Color::White);
+    place(&mut board, "D1", PieceKind::Pawn, Color::Black);
+
+    assert!(board.make_move("A1", "A5", false).is_err()); // Jumps over own pawn
+    assert!(board.make_move("A1", "A4", false).is_err()); // Captures own pawn
+    assert!(board.make_move("A1", "E1", false).is_err()); // Jumps over enemy pawn
+    assert!(board.make_move("A1", "B2", false).is_err()); // Not a rook move
+    assert!(board.make_move("A1", "D1", false).is_ok()); // Capture
+  }
+
+  #[test]
+  fn test_knight_jumps() {
+    let mut board = Board::new(Color::White);
+
+    assert!(board.make_move("G1", "F3", false).is_ok());
+    assert!(board.make_move("F3", "D2", false).is_err()); // Own pawn
+    assert!(board.make_move("B8", "C6", true).is_ok());
+    assert!(board.make_move("F3", "G5", false).is_ok());
+    assert!(board.make_move("C6", "B8", true).is_ok());
+    assert!(board.make_move("G5", "F7", false).is_ok()); // Capture
+  }
+
+  #[test]
+  fn test_bishop_collision() {
+    let mut board = Board::new(Color::White);
+
+    assert!(board.make_move("F1", "C4", false).is_err()); // Blocked by E2
+    assert!(board.make_move("E2", "E4", false).is_ok());
+    assert!(board.make_move("E7", "E5", true).is_ok());
+    assert!(board.make_move("F1", "C4", false).is_ok());
+    assert!(board.make_move("A7", "A6", true).is_ok());
+    assert!(board.make_move("C4", "G8", false).is_err()); // Blocked by F7
+    assert!(board.make_move("C4", "F7", false).is_ok()); // Capture
+  }
+
+  #[test]
+  fn test_queen_collision() {
+    let mut board = empty_board();
+
+    place(&mut board, "D1", PieceKind::Queen, Color::White);
+    place(&mut board, "D3", PieceKind::Pawn, Color::Black);
+    place(&mut board, "F3", PieceKind::Pawn, Color::White);
+    place(&mut board, "A8", PieceKind::King, Color::Black);
+
+    assert!(board.make_move("D1", "D5", false).is_err()); // Jumps over D3
+    assert!(board.make_move("D1", "G4", false).is_err()); // Jumps over F3
+    assert!(board.make_move("D1", "E3", false).is_err()); // Not a queen move
+    assert!(board.make_move("D1", "H1", false).is_ok()); // Along the row
+    assert!(board.make_move("A8", "A7", true).is_ok());
+    assert!(board.make_move("H1", "D1", false).is_ok());
+    assert!(board.make_move("A7", "A8", true).is_ok());
+    assert!(board.make_move("D1", "D3", false).is_ok()); // Capture
+  }
+
+  #[test]
+  fn test_king_moves() {
+    let mut board = empty_board();
+
+    place(&mut board, "E1", PieceKind::King, Color::White);
+    place(&mut board, "D2", PieceKind::Pawn, Color::White);
+    place(&mut board, "F2", PieceKind::Pawn, Color::Black);
+
+    assert!(board.make_move("E1", "D2", false).is_err()); // Own pawn
+    assert!(board.make_move("E1", "E3", false).is_err()); // Too far
+    assert!(board.make_move("E1", "F2", false).is_ok()); // Capture
+  }
+
+  /// Kings and rooks on their starting cells, nothing else.
+  fn castling_board() -> Board {
+    let mut board = empty_board();
+
+    for &(ref color, row) in &[(Color::White, "1"), (Color::Black, "8")] {
+      place(&mut board, &format!("E{}", row), PieceKind::King, color.clone());
+      place(&mut board, &format!("A{}", row), PieceKind::Rook, color.clone());
+      place(&mut board, &format!("H{}", row), PieceKind::Rook, color.clone());
+    }
+
+    board.set_castling("KQkq").unwrap();
+
+    board
+  }
+
+  #[test]
+  fn test_castling() {
+    let mut board = castling_board();
+
+    assert!(board.make_move("E1", "G1", false).is_ok());
+    assert_eq!(kind_at(&board, "F1"), PieceKind::Rook);
+    assert_eq!(kind_at(&board, "H1"), PieceKind::Nil);
+
+    assert!(board.make_move("E8", "C8", true).is_ok());
+    assert_eq!(kind_at(&board, "D8"), PieceKind::Rook);
+    assert_eq!(kind_at(&board, "A8"), PieceKind::Nil);
+  }
+
+  #[test]
+  fn test_castling_rights() {
+    // Pieces in between
+    let mut board = castling_board();
+    place(&mut board, "B1", PieceKind::Knight, Color::White);
+    assert!(board.make_move("E1", "C1", false).is_err());
+
+    // The king moved
+    let mut board = castling_board();
+    assert!(board.make_move("E1", "E2", false).is_ok());
+    assert!(board.make_move("E8", "E7", true).is_ok());
+    assert!(board.make_move("E2", "E1", false).is_ok());
+    assert!(board.make_move("E7", "E8", true).is_ok());
+    assert!(board.make_move("E1", "G1", false).is_err());
+
+    // The rook moved
+    let mut board = castling_board();
+    assert!(board.make_move("H1", "H2", false).is_ok());
+    assert!(board.make_move("E8", "E7", true).is_ok());
+    assert!(board.make_move("H2", "H1", false).is_ok());
+    assert!(board.make_move("E7", "E8", true).is_ok());
+    assert!(board.make_move("E1", "G1", false).is_err());
+    assert!(board.make_move("E1", "C1", false).is_ok());
+  }
+
+  #[test]
+  fn test_castling_and_check() {
+    // Out of check
+    let mut board = castling_board();
+    place(&mut board, "E4", PieceKind::Rook, Color::Black);
+    assert!(board.make_move("E1", "G1", false).is_err());
+
+    // Through check
+    let mut board = castling_board();
+    place(&mut board, "F4", PieceKind::Rook, Color::Black);
+    assert!(board.make_move("E1", "G1", false).is_err());
+    assert!(board.make_move("E1", "C1", false).is_ok());
+
+    // Into check
+    let mut board = castling_board();
+    place(&mut board, "G4", PieceKind::Rook, Color::Black);
+    assert!(board.make_move("E1", "G1", false).is_err());
+
+    // The rook may pass an attacked cell
+    let mut board = castling_board();
+    place(&mut board, "B4", PieceKind::Rook, Color::Black);
+    assert!(board.make_move("E1", "C1", false).is_ok());
+  }
+
+  #[test]
+  fn test_en_passant() {
+    let mut board = Board::new(Color::White);
+
+    for &(from, to) in &[("E2", "E4"), ("A7", "A6"), ("E4", "E5"), ("D7", "D5")] {
+      assert!(board.make_move(from, to, true).is_ok());
+    }
+
+    assert_eq!(board.to_fen().split(' ').nth(3), Some("d6"));
+    assert!(board.serialize().contains("\"en_passant\":[3,2]"));
+
+    assert!(board.make_move("E5", "D6", false).is_ok());
+    assert!(!board.has_piece((3, 3))); // D5 is gone
+    assert_eq!(board.to_fen().split(' ').nth(3), Some("-"));
+  }
+
+  #[test]
+  fn test_en_passant_expires() {
+    let mut board = Board::new(Color::White);
+
+    for &(from, to) in &[("E2", "E4"), ("A7", "A6"), ("E4", "E5"), ("D7", "D5"), ("H2", "H3"), ("H7", "H6")] {
+      assert!(board.make_move(from, to, true).is_ok());
+    }
+
+    assert!(board.make_move("E5", "D6", false).is_err());
+  }
+
+  #[test]
+  fn test_en_passant_pin() {
+    let mut board = empty_board();
+
+    // Taking en passant would open the row to the rook
+    place(&mut board, "A5", PieceKind::King, Color::White);
+    place(&mut board, "E5", PieceKind::Pawn, Color::White);
+    place(&mut board, "H5", PieceKind::Rook, Color::Black);
+    place(&mut board, "F7", PieceKind::Pawn, Color::Black);
+    place(&mut board, "E8", PieceKind::King, Color::Black);
+    board.set_turn(Color::Black);
+
+    assert!(board.make_move("F7", "F5", true).is_ok());
+    assert!(board.make_move("E5", "F6", false).is_err());
+  }
+
+  #[test]
+  fn test_turns() {
+    let mut board = Board::new(Color::White);
+
+    assert!(board.get_turn() == Color::White);
+
+    // Black can't start, not even the remote player
+    assert!(board.make_move("E7", "E5", true).is_err());
+    assert!(board.make_move("E2", "E4", false).is_ok());
+    assert!(board.get_turn() == Color::Black);
+
+    // Not twice in a row
+    assert!(board.make_move("D2", "D4", false).is_err());
+
+    // Illegal moves don't count
+    assert!(board.make_move("E7", "E4", true).is_err());
+    assert!(board.get_turn() == Color::Black);
+
+    assert!(board.make_move("E7", "E5", true).is_ok());
+    assert!(board.serialize().contains("\"turn\":\"White\""));
+  }
+
+  #[test]
+  fn test_serialization() {
+    let mut board = Board::from_fen("r3k2r/8/8/8/3pP3/8/8/R3K2R b Kq e3 5 40", Color::Black).unwrap();
+    let json = board.serialize();
+
+    // Still the grid of cells; the king and the rooks that can't castle have moved
+    assert!(json.contains("\"board\":[["));
+    assert!(json.contains("\"en_passant\":[4,5]"));
+
+    let copy: Board = serde_json::from_str(&json).unwrap();
+    assert_eq!(copy.to_fen(), board.to_fen());
+    assert!(copy.get_my_color() == Color::Black);
+
+    // Moving the king loses the rights for good
+    assert!(board.make_move("E8", "E7", false).is_ok());
+    let copy: Board = serde_json::from_str(&board.serialize()).unwrap();
+    assert_eq!(copy.to_fen(), "r6r/4k3/8/8/3pP3/8/8/R3K2R w K - 6 41");
+  }
+
+  #[test]
+  fn test_fen_export() {
+    let mut board = Board::new(Color::White);
+
+    assert_eq!(board.to_fen(), STARTING_FEN);
+
+    assert!(board.make_move("E2", "E4", false).is_ok());
+    assert_eq!(board.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+
+    assert!(board.make_move("C7", "C5", true).is_ok());
+    assert_eq!(board.to_fen(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2");
+
+    assert!(board.make_move("G1", "F3", false).is_ok());
+    assert_eq!(board.to_fen(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+    assert!(board.make_move("E8", "C7", true).is_err());
+    assert!(board.make_move("H8", "H6", true).is_err());
+  }
+
+  #[test]
+  fn test_fen_import() {
+    let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let board = Board::from_fen(kiwipete, Color::White).unwrap();
+
+    assert_eq!(board.to_fen(), kiwipete);
+
+    // Only some castling rights, black to move with en passant
+    let fen = "r3k2r/8/8/8/3pP3/8/8/R3K2R b Kq e3 5 40";
+    let mut board = Board::from_fen(fen, Color::Black).unwrap();
+
+    assert_eq!(board.to_fen(), fen);
+    assert!(board.make_move("D4", "E3", false).is_ok());
+    assert!(board.make_move("E1", "C1", true).is_err());
+    assert!(board.make_move("E1", "G1", true).is_ok());
+
+    // Counters are optional
+    let board = Board::from_fen("8/8/8/8/8/8/8/K6k w - -", Color::White).unwrap();
+    assert_eq!(board.to_fen(), "8/8/8/8/8/8/8/K6k w - - 0 1");
+  }
+
+  #[test]
+  fn test_fen_errors() {
+    let bad = [
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1", "8 ranks"),
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN w KQkq - 0 1", "7 files"),
+      ("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "skips 9"),
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1", "more than 8"),
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPXPPP/RNBQKBNR w KQkq - 0 1", "unknown piece 'X'"),
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1", "side to move"),
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq - 0 1", "rook on h1"),
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKkq - 0 1", "repeated"),
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e4 0 1", "wrong rank"),
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z9 0 1", "not a square"),
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1", "halfmove"),
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0", "fullmove"),
+      ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq", "6 fields"),
+    ];
+
+    for &(fen, error) in bad.iter() {
+      match Board::from_fen(fen, Color::White) {
+        Ok(_) => panic!("Accepted bad FEN: {}", fen),
+        Err(err) => assert!(err.to_string().contains(error), "{}: {}", fen, err),
+      };
+    }
+  }
+
+  #[test]
+  fn test_legal_moves() {
+    let board = Board::new(Color::White);
+
+    assert_eq!(board.legal_moves().len(), 20);
+    assert_eq!(board.legal_moves_from("e2".parse().unwrap()).len(), 2);
+    assert_eq!(board.legal_moves_from("e7".parse().unwrap()).len(), 0); // Not black's turn
+    assert_eq!(board.legal_moves_from("e4".parse().unwrap()).len(), 0); // Empty
+
+    let moves: Vec<String> = board.legal_moves_from("g1".parse().unwrap()).iter().map(|m| m.to_string()).collect();
+    assert_eq!(moves, vec!["g1f3", "g1h3"]);
+
+    // Kiwipete
+    let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", Color::White).unwrap();
+    let moves = board.legal_moves();
+
+    assert_eq!(moves.len(), 48);
+    assert_eq!(moves.iter().filter(|m| m.kind == MoveKind::Castling).count(), 2);
+    assert_eq!(moves.iter().filter(|m| m.kind == MoveKind::Capture).count(), 8);
+  }
+
+  #[test]
+  fn test_legal_moves_special() {
+    // En passant and promotions (4 pieces, 2 cells)
+    let board = Board::from_fen("1n2k3/P7/8/3pP3/8/8/8/4K3 w - d6 0 1", Color::White).unwrap();
+    let moves = board.legal_moves();
+
+    let en_passant: Vec<String> = moves.iter().filter(|m| m.kind == MoveKind::EnPassant).map(|m| m.to_string()).collect();
+    assert_eq!(en_passant, vec!["e5d6"]);
+
+    let promotions: Vec<String> = moves.iter().filter(|m| m.promotion.is_some()).map(|m| m.to_string()).collect();
+    assert_eq!(promotions, vec!["a7a8q", "a7a8r", "a7a8b", "a7a8n", "a7b8q", "a7b8r", "a7b8b", "a7b8n"]);
+
+    // Double check, only the king can move
+    let board = Board::from_fen("4k3/8/8/8/8/5n2/8/r3K3 w - - 0 1", Color::White).unwrap();
+    let moves = board.legal_moves();
+
+    assert!(board.is_in_check(&Color::White));
+    assert!(moves.iter().all(|m| m.from == Square::new(4, 0).unwrap()));
+    assert_eq!(moves.len(), 2); // E2 and F2
+  }
+
+  #[test]
+  fn test_divide() {
+    let board = Board::new(Color::White);
+    let divide = board.divide(2);
+
+    assert_eq!(divide.len(), 20);
+    assert!(divide.iter().all(|&(_, nodes)| nodes == 20));
+    assert_eq!(board.perft(2), 400);
+  }
+
+  #[test]
+  fn test_insufficient_material() {
+    let cases = [
+      ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true),
+      ("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", true),
+      ("4k3/8/8/8/8/8/8/4KB2 w - - 0 1", true),
+      ("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", true), // Both on light cells
+      ("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1", false), // Light and dark
+      ("4k3/8/8/8/8/8/8/3NKN2 w - - 0 1", false),
+      ("4kn2/8/8/8/8/8/8/4KB2 w - - 0 1", false),
+      ("4k3/8/8/8/8/8/8/4KR2 w - - 0 1", false),
+      ("4k3/p7/8/8/8/8/8/4K3 w - - 0 1", false),
+    ];
+
+    for &(fen, insufficient) in cases.iter() {
+      let board = Board::from_fen(fen, Color::White).unwrap();
+      assert_eq!(board.has_insufficient_material(), insufficient, "{}", fen);
+    }
+  }
+
+  #[test]
+  fn test_hash() {
+    let mut board = Board::new(Color::White);
+    let start = board.hash();
+
+    // Same position, however it was reached
+    for &(from, to) in &[("G1", "F3"), ("G8", "F6"), ("F3", "G1"), ("F6", "G8")] {
+      assert!(board.make_move(from, to, true).is_ok());
+    }
+
+    assert_eq!(board.hash(), start);
+    assert_eq!(Board::from_fen(STARTING_FEN, Color::Black).unwrap().hash(), start);
+
+    // Polyglot's published keys
+    assert_eq!(start, 0x463b96181691fc9c);
+
+    let keys = [
+      ("E2", "E4", 0x823c9b50fd114196),
+      ("D7", "D5", 0x0756b94461c50fb0),
+      ("E4", "E5", 0x662fafb965db29d4),
+      ("F7", "F5", 0x22a48b5a8e47ff78),
+      ("E1", "E2", 0x652a607ca3f242c1),
+      ("E8", "F7", 0x00fdd303c946bdd9),
+    ];
+
+    let mut polyglot = Board::new(Color::White);
+
+    for &(from, to, key) in &keys {
+      assert!(polyglot.make_move(from, to, true).is_ok());
+      assert_eq!(polyglot.hash(), key, "{}{}", from, to);
+    }
+
+    let mut polyglot = Board::new(Color::White);
+
+    for &(from, to) in &[("A2", "A4"), ("B7", "B5"), ("H2", "H4"), ("B5", "B4"), ("C2", "C4")] {
+      assert!(polyglot.make_move(from, to, true).is_ok());
+    }
+
+    assert_eq!(polyglot.hash(), 0x3c8123ea7b067637);
+
+    for &(from, to) in &[("B4", "C3"), ("A1", "A3")] {
+      assert!(polyglot.make_move(from, to, true).is_ok());
+    }
+
+    assert_eq!(polyglot.hash(), 0x5c3f9b829b279560);
+
+    // The side to move, castling rights and en passant all count
+    let fen = "r3k2r/8/8/8/3pP3/8/8/R3K2R b KQkq e3 0 1";
+    let hash = Board::from_fen(fen, Color::White).unwrap().hash();
+
+    for other in &["r3k2r/8/8/8/3pP3/8/8/R3K2R w KQkq - 0 1", "r3k2r/8/8/8/3pP3/8/8/R3K2R b Kkq e3 0 1", "r3k2r/8/8/8/3pP3/8/8/R3K2R b KQkq - 0 1"] {
+      assert!(Board::from_fen(other, Color::White).unwrap().hash() != hash, "{}", other);
+    }
+
+    // Unless nobody can take en passant, and the move counters never do
+    let hash = Board::from_fen("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1", Color::White).unwrap().hash();
+    assert_eq!(Board::from_fen("4k3/8/8/8/4P3/8/8/4K3 b - - 7 30", Color::White).unwrap().hash(), hash);
+
+    // Saving keeps it
+    let copy: Board = serde_json::from_str(&board.serialize()).unwrap();
+    assert_eq!(copy.hash(), start);
+  }
+
+  #[test]
+  fn test_promotion() {
+    let mut board = empty_board();
+
+    place(&mut board, "E1", PieceKind::King, Color::White);
+    place(&mut board, "E8", PieceKind::King, Color::Black);
+    place(&mut board, "B7", PieceKind::Pawn, Color::White);
+    place(&mut board, "A8", PieceKind::Rook, Color::Black);
+
+    assert!(board.make_move("B7", "B8", false).is_err()); // Must pick a piece
+    assert!(board.make_move_with_promotion("B7", "B8", Some("K"), false).is_err()); // Can't be a king
+    assert!(board.make_move_with_promotion("E1", "E2", Some("Q"), false).is_err()); // Not a pawn
+
+    assert!(board.make_move_with_promotion("B7", "A8", Some("n"), false).is_ok());
+    assert_eq!(board.piece_at("a8".parse().unwrap()), Some(Piece{kind: PieceKind::Knight, color: Color::White}));
+
+    // Built by hand, a move could still ask for a king or a pawn
+    let mut board = empty_board();
+
+    place(&mut board, "E1", PieceKind::King, Color::White);
+    place(&mut board, "H6", PieceKind::King, Color::Black);
+    place(&mut board, "E7", PieceKind::Pawn, Color::White);
+
+    for kind in vec![PieceKind::King, PieceKind::Pawn] {
+      let m = Move::new("e7".parse().unwrap(), "e8".parse().unwrap(), Some(kind));
+
+      match board.play(&m, false) {
+        Err(ChessError::IllegalMove(_)) => (),
+        other => panic!("Expected an illegal move: {:?}", other),
+      };
+    }
+
+    assert_eq!(board.piece_at("e8".parse().unwrap()), None);
+    assert!(board.play(&Move::new("e7".parse().unwrap(), "e8".parse().unwrap(), Some(PieceKind::Rook)), false).is_ok());
+  }
+
+  #[test]
+  fn test_check() {
+    let mut board = empty_board();
+
+    place(&mut board, "E1", PieceKind::King, Color::White);
+    place(&mut board, "E2", PieceKind::Bishop, Color::White);
+    place(&mut board, "E8", PieceKind::Rook, Color::Black);
+    place(&mut board, "A8", PieceKind::King, Color::Black);
+
+    assert!(!board.is_in_check(&Color::White));
+
+    // The bishop is pinned
+    assert!(board.make_move("E2", "D3", false).is_err());
+
+    // The king can't walk into the rook
+    assert!(board.make_move("E1", "E2", false).is_err());
+    assert!(board.make_move("E1", "D2", false).is_ok());
+
+    // Pawns attack diagonally forward only
+    place(&mut board, "C4", PieceKind::Pawn, Color::Black);
+    assert!(board.is_attacked((3, 5), &Color::Black)); // D3
+    assert!(!board.is_attacked((3, 3), &Color::Black)); // D5
+    assert!(board.make_move("D2", "D3", false).is_err());
+  }
+
+  #[test]
+  fn test_no_legal_moves() {
+    let mut board = empty_board();
+
+    // Stalemate: black king in the corner, boxed in by the queen
+    place(&mut board, "A8", PieceKind::King, Color::Black);
+    place(&mut board, "B6", PieceKind::Queen, Color::White);
+    place(&mut board, "H1", PieceKind::King, Color::White);
+
+    assert!(!board.is_in_check(&Color::Black));
+    assert!(!board.has_legal_moves(&Color::Black));
+    assert!(board.has_legal_moves(&Color::White));
+
+    // Checkmate: the queen steps up, protected by the king
+    place(&mut board, "H1", PieceKind::Nil, Color::Nil);
+    place(&mut board, "C6", PieceKind::King, Color::White);
+    assert!(board.make_move("B6", "B7", false).is_ok());
+
+    assert!(board.is_in_check(&Color::Black));
+    assert!(!board.has_legal_moves(&Color::Black));
+  }
+
+  #[test]
+  fn test_display_orientation() {
+    // Without the terminal colors
+    let plain = |board: &Board| {
+      let text = board.to_string();
+      let mut plain = String::new();
+      let mut escape = false;
+
+      for c in text.chars() {
+        if c == '\x1b' {
+          escape = true;
+        }
+
+        else if escape {
+          escape = c != 'm';
+        }
+
+        else {
+          plain.push(c);
+        }
+      }
+
+      plain.split("\r\n\r\n").map(|row| row.replace(" ", "")).collect::<Vec<String>>()
+    };
+
+    let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1";
+
+    let white = plain(&Board::from_fen(fen, Color::White).unwrap());
+    let black = plain(&Board::from_fen(fen, Color::Black).unwrap());
+
+    // White sees rank 8 first and A on the left
+    assert!(white[0].starts_with("8"));
+    assert!(white[7].starts_with("1"));
+    assert_eq!(white[8], "ABCDEFGH");
+
+    // Black sees the board turned around
+    assert!(black[0].starts_with("1"));
+    assert!(black[7].starts_with("8"));
+    assert_eq!(black[8], "HGFEDCBA");
+
+    // Every row is mirrored
+    for row in 0..8 {
+      let white_row: String = white[7 - row][1..].chars().rev().collect();
+
+      assert_eq!(black[row][1..], white_row);
+    }
+  }
+
+  #[test]
+  fn test_editor() {
+    let square = |notation: &str| notation.parse::<Square>().unwrap();
+    let piece = |letter: &str| letter.parse::<Piece>().unwrap();
+
+    let mut board = Board::new(Color::White);
+    board.clear();
+
+    assert_eq!(board.to_fen(), "8/8/8/8/8/8/8/8 w - - 0 1");
+    assert!(board.validate().is_err());
+
+    // Kings and rooks set up by hand don't castle until told so
+    board.place(square("e1"), piece("K"));
+    board.place(square("h1"), piece("R"));
+    board.place(square("e8"), piece("k"));
+    board.place(square("d7"), piece("p"));
+
+    assert_eq!(board.to_fen(), "4k3/3p4/8/8/8/8/8/4K2R w - - 0 1");
+    assert!(board.validate().is_ok());
+
+    assert!(board.set_castling("KQ").is_err());
+    assert!(board.set_castling("Kx").is_err());
+    assert!(board.set_castling("K").is_ok());
+    assert!(board.legal_moves().iter().any(|m| m.kind == MoveKind::Castling));
+
+    // Removing the rook takes the right away
+    assert_eq!(board.remove(square("h1")), Some(piece("R")));
+    assert_eq!(board.remove(square("h1")), None);
+    assert_eq!(board.to_fen(), "4k3/3p4/8/8/8/8/8/4K3 w - - 0 1");
+
+    // Black to move
+    board.set_turn(Color::Black);
+    assert_eq!(board.get_turn(), Color::Black);
+    assert!(board.validate().is_ok());
+
+    // White in check while black moves
+    board.place(square("e7"), piece("q"));
+    assert!(board.validate().is_err());
+    board.set_turn(Color::White);
+    assert!(board.validate().is_ok());
+
+    // Pawns on the back ranks
+    board.place(square("a8"), piece("P"));
+    assert!(board.validate().is_err());
+    board.place(square("a8"), Piece{kind: PieceKind::Nil, color: Color::Nil});
+    assert!(board.validate().is_ok());
+
+    // Two kings
+    board.place(square("a1"), piece("K"));
+    assert!(board.validate().is_err());
   }
 }
@@ -0,0 +1,331 @@
+/// Command line of the rust_chess binary.
+
+use board::{Board, Color, STARTING_FEN};
+use clock::TimeControl;
+use engine;
+use error::ChessError;
+use game::{Opponent, Settings};
+use protocol::ColorChoice;
+
+/// How to use the binary, printed for help and mistakes.
+pub const USAGE: &str = "Usage:
+  rust_chess [play] [--vs self|engine] [options]   Play on this computer
+  rust_chess edit [--vs self|engine] [options]     Set up the position, then play it
+  rust_chess host tcp://0.0.0.0:54345 [options]    Wait for the other player to connect
+  rust_chess join tcp://127.0.0.1:54345 [options]  Connect to the other player (or http://server)
+  rust_chess convert <from> <to> [--game N]        Convert between PGN (.pgn) and saved games (.json)
+  rust_chess perft <depth> [fen]                   Count positions to check move generation
+  rust_chess analyze [fen] [--depth N]             Show the engine's best line and score
+
+Options:
+  --color white|black|random
+                        The side you want; the host decides in network games
+  --time <control>      Time control, e.g. 5+3, 15d5, 40/90+30,30+30
+  --fen <fen>           Start from this position
+  --save <path>         Save the game here when it ends or you leave
+  --depth <N>           Plies the engine looks ahead when analyzing
+  --headless            Play in the terminal, without a window";
+
+/// What the binary was asked to do.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+  /// Play a game, in a window unless headless.
+  Play{settings: Settings, headless: bool},
+
+  /// Convert a game between PGN and the save format; `game` counts from 1.
+  Convert{from: String, to: String, game: usize},
+
+  /// Count positions reachable in `depth` moves.
+  Perft{depth: usize, fen: String},
+
+  /// Show what the engine would play, looking `depth` plies ahead.
+  Analyze{fen: String, depth: usize},
+
+  /// Show the usage.
+  Help,
+}
+
+/// Read the command line.
+///
+/// Parameters:
+/// `args`: &[String], without the program name
+///
+/// Return: Result<Command, String> (what is wrong with the arguments)
+pub fn parse(args: &[String]) -> Result<Command, String> {
+  let mut positional = Vec::new();
+  let mut settings = Settings::default();
+  let mut vs = None;
+  let mut headless = false;
+  let mut game = None;
+  let mut depth = None;
+
+  let mut i = 0;
+
+  while i < args.len() {
+    let arg = args[i].as_str();
+
+    if !arg.starts_with("--") {
+      positional.push(args[i].clone());
+      i += 1;
+      continue;
+    }
+
+    // Flags without a value
+    if arg == "--headless" {
+      headless = true;
+      i += 1;
+      continue;
+    }
+
+    if arg == "--help" {
+      return Ok(Command::Help);
+    }
+
+    let value = match args.get(i + 1) {
+      Some(value) => value.clone(),
+      None => return Err(format!("{} needs a value.", arg)),
+    };
+
+    match arg {
+      "--vs" => vs = Some(value),
+      "--color" | "--colour" => settings.color = Some(value.parse::<ColorChoice>()?),
+      "--time" => settings.time_control = Some(value.parse::<TimeControl>().map_err(|err| err.to_string())?),
+      "--save" => settings.save_path = Some(value),
+
+      "--fen" => {
+        Board::from_fen(&value, Color::White).map_err(|err: ChessError| err.to_string())?;
+        settings.fen = Some(value);
+      },
+
+      "--game" => game = match value.parse::<usize>() {
+        Ok(game) if game > 0 => Some(game),
+        _ => return Err(format!("--game is a number from 1, not {}.", value)),
+      },
+
+      "--depth" => depth = match value.parse::<usize>() {
+        Ok(depth) if depth > 0 => Some(depth),
+        _ => return Err(format!("--depth is a number from 1, not {}.", value)),
+      },
+
+      other => return Err(format!("Unknown option {}.", other)),
+    };
+
+    i += 2;
+  }
+
+  let command = match positional.first() {
+    Some(command) => command.as_str(),
+    None => "play",
+  };
+
+  // Options that only make sense for some commands
+  if vs.is_some() && command != "play" && command != "edit" {
+    return Err(String::from("--vs is only for play and edit."));
+  }
+
+  if game.is_some() && command != "convert" {
+    return Err(String::from("--game is only for convert."));
+  }
+
+  if depth.is_some() && command != "analyze" {
+    return Err(String::from("--depth is only for analyze."));
+  }
+
+  match command {
+    "play" | "edit" => {
+      // play is the default command, so it may not be there
+      if positional.len() > 1 {
+        return Err(format!("Unexpected argument {}.", positional[1]));
+      }
+
+      settings.opponent = match vs.as_ref().map(|vs| vs.as_str()) {
+        None | Some("self") => Opponent::Local,
+        Some("engine") => Opponent::Engine,
+        Some(other) => return Err(format!("Can't play against {}, only self or engine.", other)),
+      };
+
+      settings.edit = command == "edit";
+
+      Ok(Command::Play{settings, headless})
+    },
+
+    "host" | "join" => {
+      expect_arguments(&positional, 2)?;
+
+      let addr = positional[1].clone();
+
+      settings.opponent = match command {
+        "host" => Opponent::Host(addr),
+        _ => Opponent::Join(addr),
+      };
+
+      Ok(Command::Play{settings, headless})
+    },
+
+    "convert" => {
+      expect_arguments(&positional, 3)?;
+
+      Ok(Command::Convert{from: positional[1].clone(), to: positional[2].clone(), game: game.unwrap_or(1)})
+    },
+
+    "perft" => {
+      let depth = match positional.get(1).map(|depth| depth.parse::<usize>()) {
+        Some(Ok(depth)) => depth,
+        _ => return Err(String::from("perft needs a depth, e.g. perft 4.")),
+      };
+
+      // The FEN can be the rest of the arguments, or --fen
+      let fen = match positional.len() > 2 {
+        true => positional[2..].join(" "),
+        false => settings.fen.unwrap_or(String::from(STARTING_FEN)),
+      };
+
+      Ok(Command::Perft{depth, fen})
+    },
+
+    "analyze" => {
+      // The FEN can be the rest of the arguments, or --fen
+      let fen = match positional.len() > 1 {
+        true => positional[1..].join(" "),
+        false => settings.fen.unwrap_or(String::from(STARTING_FEN)),
+      };
+
+      Board::from_fen(&fen, Color::White).map_err(|err: ChessError| err.to_string())?;
+
+      Ok(Command::Analyze{fen, depth: depth.unwrap_or(engine::DEFAULT_DEPTH)})
+    },
+
+    "help" => Ok(Command::Help),
+
+    other => Err(format!("Unknown command {}.", other)),
+  }
+}
+
+/// Check the command got exactly `count` arguments, counting itself.
+fn expect_arguments(positional: &[String], count: usize) -> Result<(), String> {
+  if positional.len() < count {
+    Err(format!("{} needs more arguments.", positional[0]))
+  }
+
+  else if positional.len() > count {
+    Err(format!("Unexpected argument {}.", positional[count]))
+  }
+
+  else {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn parse_str(line: &str) -> Result<Command, String> {
+    let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+
+    parse(&args)
+  }
+
+  #[test]
+  fn test_play() {
+    assert_eq!(parse_str(""), Ok(Command::Play{settings: Settings::default(), headless: false}));
+
+    match parse_str("play --vs engine --color black --time 5+3 --save game.json --headless").unwrap() {
+      Command::Play{settings, headless} => {
+        assert_eq!(settings.opponent, Opponent::Engine);
+        assert_eq!(settings.color, Some(ColorChoice::Black));
+        assert_eq!(settings.time_control.unwrap().to_string(), "5+3");
+        assert_eq!(settings.save_path, Some(String::from("game.json")));
+        assert!(headless);
+      },
+      other => panic!("Expected to play: {:?}", other),
+    };
+
+    // A FEN is several arguments on the command line, one when quoted
+    let args: Vec<String> = vec!["--fen", "4k3/8/8/8/8/8/8/R3K3 b - - 10 40"].into_iter().map(String::from).collect();
+
+    match parse(&args).unwrap() {
+      Command::Play{settings, ..} => assert_eq!(settings.fen, Some(String::from("4k3/8/8/8/8/8/8/R3K3 b - - 10 40"))),
+      other => panic!("Expected to play: {:?}", other),
+    };
+  }
+
+  #[test]
+  fn test_network() {
+    match parse_str("host tcp://0.0.0.0:54345").unwrap() {
+      Command::Play{settings, ..} => {
+        assert_eq!(settings.opponent, Opponent::Host(String::from("tcp://0.0.0.0:54345")));
+        assert_eq!(settings.color, None);
+      },
+      other => panic!("Expected to host: {:?}", other),
+    };
+
+    match parse_str("join http://server --color random").unwrap() {
+      Command::Play{settings, ..} => {
+        assert_eq!(settings.opponent, Opponent::Join(String::from("http://server")));
+        assert_eq!(settings.color, Some(ColorChoice::Random));
+      },
+      other => panic!("Expected to join: {:?}", other),
+    };
+  }
+
+  #[test]
+  fn test_other_commands() {
+    assert_eq!(
+      parse_str("convert games.pgn game.json --game 2"),
+      Ok(Command::Convert{from: String::from("games.pgn"), to: String::from("game.json"), game: 2})
+    );
+
+    assert_eq!(parse_str("perft 3"), Ok(Command::Perft{depth: 3, fen: String::from(STARTING_FEN)}));
+    assert_eq!(
+      parse_str("perft 2 8/8/8/8/8/8/8/K6k w - - 0 1"),
+      Ok(Command::Perft{depth: 2, fen: String::from("8/8/8/8/8/8/8/K6k w - - 0 1")})
+    );
+
+    match parse_str("edit --vs engine").unwrap() {
+      Command::Play{settings, ..} => {
+        assert_eq!(settings.opponent, Opponent::Engine);
+        assert!(settings.edit);
+      },
+      other => panic!("Expected to edit: {:?}", other),
+    };
+
+    assert_eq!(parse_str("analyze"), Ok(Command::Analyze{fen: String::from(STARTING_FEN), depth: engine::DEFAULT_DEPTH}));
+    assert_eq!(
+      parse_str("analyze 8/8/8/8/8/8/8/K6k w - - 0 1 --depth 5"),
+      Ok(Command::Analyze{fen: String::from("8/8/8/8/8/8/8/K6k w - - 0 1"), depth: 5})
+    );
+
+    assert_eq!(parse_str("help"), Ok(Command::Help));
+    assert_eq!(parse_str("play --help"), Ok(Command::Help));
+  }
+
+  #[test]
+  fn test_errors() {
+    let bad = [
+      "fly",
+      "play --vs martian",
+      "play extra",
+      "edit extra",
+      "host",
+      "host --vs engine tcp://0.0.0.0:54345",
+      "convert games.pgn",
+      "play --game 2",
+      "convert a.pgn b.json --game 0",
+      "perft",
+      "perft deep",
+      "analyze nonsense",
+      "analyze --depth 0",
+      "perft 3 --depth 2",
+      "--color green",
+      "--time soon",
+      "--fen nonsense",
+      "--save",
+      "--colors white",
+    ];
+
+    for line in bad.iter() {
+      assert!(parse_str(line).is_err(), "{}", line);
+    }
+  }
+}
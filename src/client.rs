@@ -3,15 +3,18 @@ extern crate regex;
 use regex::RegexSet;
 
 // Game
-// use board::Color;
+use board::Color;
 
 use connection::{
   Connection, EchoConnection, TcpConnection,
-  SelfConnection, HttpConnection,
+  SelfConnection, HttpConnection, EngineConnection,
 };
 
 // Messages and Regexes
-use protocol::{Message, MessageRegex};
+use protocol::{self, Message, MessageRegex, ColorChoice};
+
+// Errors
+use error::ChessError;
 
 // Client
 pub struct Client {
@@ -21,6 +24,7 @@ pub struct Client {
   // in_game: bool,
   // color: Color,
   pub host: bool,
+  pub local: bool, // The other player is on this computer, so moves can be taken back
   name: String,
 }
 
@@ -29,9 +33,12 @@ impl Client {
   /// Create a new client for multiplayer.
   ///
   /// Parameters:
-  /// `game`: Game (will take ownership)
-  pub fn new(server: &str) -> Client {
+  /// `server`: &str, e.g. tcp://127.0.0.1:54345, http://server, echo or self
+  ///
+  /// Return: Result<Client, ChessError> (a connection error if the server can't be reached)
+  pub fn new(server: &str) -> Result<Client, ChessError> {
     let connection: Box<Connection>;
+    let mut local = false;
 
     if server.starts_with("echo") {
       connection = Box::new(EchoConnection::new());
@@ -40,7 +47,7 @@ impl Client {
     else if server.starts_with("tcp://") {
       let tcp_connection = match TcpConnection::new(&server[6..]) {
         Ok(conn) => conn,
-        Err(err) => panic!("Could not connect to server: {}", err),
+        Err(err) => return Err(ChessError::Connection(format!("Could not connect to server: {}", err))),
       };
 
       connection = Box::new(tcp_connection);
@@ -49,7 +56,7 @@ impl Client {
     else if server.starts_with("http://") {
       let http_client = match HttpConnection::new(&server, "my_very_random_name_1") {
         Ok(client) => client,
-        Err(err) => panic!("Could not connect to server: {}", err),
+        Err(err) => return Err(ChessError::Connection(format!("Could not connect to server: {}", err))),
       };
 
       connection = Box::new(http_client);
@@ -57,30 +64,54 @@ impl Client {
 
     else {
       connection = Box::new(SelfConnection::new());
+      local = true;
     }
         
-    Client{
+    Ok(Client{
       // server: String::from(server),
       connection,
       host: false,
+      local,
       name: String::default(),
 
       // color: Color::Nil,
-    }
+    })
+  }
+
+  /// Play against the engine.
+  ///
+  /// Parameters:
+  /// `fen`: &str, the position the game starts in
+  ///
+  /// Return: Result<Client, ChessError> (a parse error if the FEN is bad)
+  pub fn engine(fen: &str) -> Result<Client, ChessError> {
+    let connection = match EngineConnection::new(fen) {
+      Ok(connection) => connection,
+      Err(err) => return Err(ChessError::Parse(err)),
+    };
+
+    Ok(Client{
+      connection: Box::new(connection),
+      host: false,
+      local: true,
+      name: String::from("Engine"),
+    })
   }
 
   /// Host a peer-to-peer game.
   ///
   /// Parameters:
   /// `addr`: &str Properly formatted address, e.g. tcp://0.0.0.0:54345
-  pub fn host(addr: &str) -> Client {
+  ///
+  /// Return: Result<Client, ChessError> (a connection error if nobody could connect)
+  pub fn host(addr: &str) -> Result<Client, ChessError> {
     let connection: Box<Connection>;
 
     // TCP
     if addr.starts_with("tcp://") {
       let listener = match TcpConnection::host(&addr[6..]) {
         Ok(conn) => conn,
-        Err(err) => panic!("Could not create server: {}",  err),
+        Err(err) => return Err(ChessError::Connection(format!("Could not create server: {}", err))),
       };
 
       connection = Box::new(listener);
@@ -91,11 +122,12 @@ impl Client {
       connection = Box::new(EchoConnection::new());
     }
 
-    Client{
+    Ok(Client{
       connection,
       host: true,
+      local: false,
       name: String::default(),
-    }
+    })
   }
 
   /// Send a message to the remote peer
@@ -121,6 +153,18 @@ impl Client {
       Message::MakeMove => {
         format!("{} {}", Message::MakeMove, payload)
       },
+
+      Message::Undo => {
+        Message::Undo.to_string()
+      },
+
+      Message::Clock => {
+        format!("{} {}", Message::Clock, payload)
+      },
+
+      Message::Color => {
+        format!("{} {}", Message::Color, payload)
+      },
     };
 
     println!("Sending message in client: {}", message);
@@ -129,21 +173,31 @@ impl Client {
   }
 
   /// Wait for answer from peer and block until it arrives.
-  pub fn wait_for_message(&mut self) -> (Message, String) {
+  ///
+  /// Return: Result<(Message, String), ChessError> (a connection error if the pipe broke
+  /// or the peer sent something we don't understand)
+  pub fn wait_for_message(&mut self) -> Result<(Message, String), ChessError> {
     // This will block until something arrives
     // over the pipe. This may not always be what we want
     // so we can use Connection::get_message() isntead.
-    let message = self.connection.wait_for_message().unwrap(); // TODO: Handle error
+    let message = match self.connection.wait_for_message() {
+      Ok(message) => message,
+      Err(err) => return Err(ChessError::Connection(err)),
+    };
 
     // Handle the reply
-    self.handle_reply(&message).unwrap()
+    self.handle_reply(&message)
   }
 
   /// Async wait_for_message (non-blocking)
-  pub fn get_message(&mut self) -> Result<(Message, String), String> {
+  ///
+  /// Return: Result<Option<(Message, String)>, ChessError> (None if nothing has arrived yet,
+  /// a connection error if the pipe broke, a parse error if the peer sent something we don't understand)
+  pub fn get_message(&mut self) -> Result<Option<(Message, String)>, ChessError> {
     match self.connection.get_message() {
-      Ok(message) => self.handle_reply(&message),
-      Err(_) => Err(String::from("No message received yet.")),
+      Ok(Some(message)) => self.handle_reply(&message).map(Some),
+      Ok(None) => Ok(None),
+      Err(err) => Err(ChessError::Connection(err)),
     }
   }
 
@@ -151,7 +205,7 @@ impl Client {
   ///
   /// Parameters:
   /// `message`: &str reply from peer
-  fn handle_reply(&mut self, message: &str) -> Result<(Message, String), String> {
+  fn handle_reply(&mut self, message: &str) -> Result<(Message, String), ChessError> {
 
     lazy_static! {
       static ref MESSAGES: RegexSet =  RegexSet::new(&[
@@ -159,6 +213,9 @@ impl Client {
         MessageRegex::MakeMove.to_string(),
         MessageRegex::Hello.to_string(),
         MessageRegex::BadMessage.to_string(),
+        MessageRegex::Undo.to_string(),
+        MessageRegex::Clock.to_string(),
+        MessageRegex::Color.to_string(),
       ]).unwrap();
     }
     
@@ -166,7 +223,7 @@ impl Client {
 
     // Should match only one message    
     if matches.len() != 1 {
-      return Err(format!("Unknown message received: {}", message));
+      return Err(ChessError::Parse(format!("Unknown message received: {}", message)));
     }
 
     let message_match = matches.iter().next();
@@ -181,6 +238,9 @@ impl Client {
       },
 
       Some(&2) => {
+        // The other player introduced themselves
+        self.name = String::from(&message[6..]);
+
         Ok((Message::Hello, String::from(&message[6..])))
       },
 
@@ -188,32 +248,110 @@ impl Client {
         Ok((Message::BadMessage, String::from("")))
       },
 
-      Some(&_) => {
-        panic!("Client > Handle reply : Received valid message that is not handled by client.");
+      Some(&4) => {
+        Ok((Message::Undo, String::from("")))
+      },
+
+      Some(&5) => {
+        Ok((Message::Clock, String::from(&message[6..])))
+      },
+
+      Some(&6) => {
+        Ok((Message::Color, String::from(&message[6..])))
+      },
+
+      Some(&index) => {
+        Err(ChessError::Connection(format!("Got message matching pattern {} that the client doesn't handle.", index)))
       },
       
       None => {
-        Err(String::from("Got message matching nothing."))
+        Err(ChessError::Connection(String::from("Got message matching nothing.")))
       },
     }
   }
 
+  /// Agree on colors with the other player before the game starts.
+  /// The guest says hello and asks for a color; the host decides,
+  /// says hello back and tells the guest what they play.
+  ///
+  /// Parameters:
+  /// `name`: &str, our name, only letters and numbers are sent
+  /// `choice`: Option<&ColorChoice>, the side we want, None if we don't mind
+  ///
+  /// Return: Result<Color, ChessError> (a connection error if the other player left or said something else)
+  pub fn negotiate_color(&mut self, name: &str, choice: Option<&ColorChoice>) -> Result<Color, ChessError> {
+    let name: String = name.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+
+    let name = match name.is_empty() {
+      true => String::from("Player"),
+      false => name,
+    };
+
+    if !self.host {
+      let request = choice.map_or(String::from("any"), |choice| choice.to_string());
+
+      self.send_message(Message::Hello, &name);
+      self.send_message(Message::Color, &request);
+    }
+
+    loop {
+      let (message, payload) = self.wait_for_message()?;
+
+      match message {
+        // Names come with the hello
+        Message::Hello => continue,
+
+        Message::Color => {
+          if !self.host {
+            return match payload.as_str() {
+              "white" => Ok(Color::White),
+              "black" => Ok(Color::Black),
+              other => Err(ChessError::Connection(format!("The host picked no color: {}", other))),
+            };
+          }
+
+          let color = protocol::host_color(choice, &payload);
+
+          let guest = match color {
+            Color::White => ColorChoice::Black,
+            _ => ColorChoice::White,
+          };
+
+          self.send_message(Message::Hello, &name);
+          self.send_message(Message::Color, &guest.to_string());
+
+          return Ok(color);
+        },
+
+        Message::Bye => return Err(ChessError::Connection(String::from("The other player left."))),
+
+        other => return Err(ChessError::Connection(format!("Expected a color, got {}.", other))),
+      };
+    }
+  }
+
   pub fn set_name(&mut self, name: &str) {
     self.name = String::from(name);
   }
+
+  /// The other player's name, from their hello message, empty if they didn't say.
+  pub fn get_name(&self) -> &str {
+    &self.name
+  }
 }
 
 #[cfg(test)]
 mod test {
 
   use client::Client;
+  use error::ChessError;
   use protocol::*;
 
   // Test regex handling of replies
   #[test]
   fn test_messages() {
     // Create a dummy client
-    let mut client = Client::new("echo");
+    let mut client = Client::new("echo").unwrap();
 
     // Message missing payload
     match client.handle_reply(&Message::MakeMove.to_string()) {
@@ -227,16 +365,69 @@ mod test {
       Err(err) => panic!("Made a valid move. {}", err),
     };
 
+    // Promotion
+    match client.handle_reply(&format!("{} e7e8q", Message::MakeMove)) {
+      Ok(input) => assert_eq!(input.1, "e7e8q"),
+      Err(err) => panic!("Promotion is a valid move. {}", err),
+    };
+
+    // Algebraic notation
+    match client.handle_reply(&format!("{} Nxf7+", Message::MakeMove)) {
+      Ok(input) => assert_eq!(input.1, "Nxf7+"),
+      Err(err) => panic!("Algebraic notation is a valid move. {}", err),
+    };
+
+    // Castling
+    match client.handle_reply(&format!("{} O-O-O", Message::MakeMove)) {
+      Ok(input) => assert_eq!(input.1, "O-O-O"),
+      Err(err) => panic!("Castling is a valid move. {}", err),
+    };
+
     // Good message with no payload
     match client.handle_reply(&Message::Bye.to_string()) {
       Ok(input) => assert_eq!(input.1, "exit"),
       Err(err) => panic!("Valid good bye message. {}", err),
     };
 
+    // Taking back moves
+    match client.handle_reply(&Message::Undo.to_string()) {
+      Ok(input) => assert_eq!(input.0, Message::Undo),
+      Err(err) => panic!("Valid undo message. {}", err),
+    };
+
+    // Clock times in milliseconds, white then black
+    match client.handle_reply(&format!("{} 299500 300000", Message::Clock)) {
+      Ok(input) => assert_eq!(input, (Message::Clock, String::from("299500 300000"))),
+      Err(err) => panic!("Valid clock message. {}", err),
+    };
+
+    // Asking for a color, and the host's answer
+    match client.handle_reply(&format!("{} any", Message::Color)) {
+      Ok(input) => assert_eq!(input, (Message::Color, String::from("any"))),
+      Err(err) => panic!("Valid color message. {}", err),
+    };
+
+    match client.handle_reply(&format!("{} green", Message::Color)) {
+      Ok(msg) => panic!("Not supposed to accept this message: {}", msg.0),
+      Err(_) => (),
+    };
+
+    match client.handle_reply(&format!("{} 299500", Message::Clock)) {
+      Ok(msg) => panic!("Not supposed to accept this message: {}", msg.0),
+      Err(_) => (),
+    };
+
     // Bad message with payload
     match client.handle_reply(&format!("{} random_text", Message::Bye)) {
       Ok(msg) => panic!("Not supposed to accept this message: {}", msg.0),
-      Err(_) => (),
+      Err(ChessError::Parse(_)) => (),
+      Err(err) => panic!("Expected a parse error: {}", err),
+    };
+
+    // Nothing has arrived, which isn't an error
+    match client.get_message() {
+      Ok(None) => (),
+      other => panic!("Expected no message: {:?}", other),
     };
   }
 }
@@ -0,0 +1,525 @@
+/// Chess clocks: sudden death, Fischer increment, simple and Bronstein delay,
+/// and controls with several stages, e.g. 40/90+30,30+30.
+
+// Display trait
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// Regex
+extern crate regex;
+use regex::Regex;
+
+use board::Color;
+use error::ChessError;
+
+/// What a player gets for each move they make.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bonus {
+  /// Nothing, sudden death.
+  None,
+
+  /// Added after every move (Fischer), written +30.
+  Increment(Duration),
+
+  /// The clock waits this long before it counts down (simple or US delay), written d5.
+  Delay(Duration),
+
+  /// The time used is given back after the move, up to this much (Bronstein), written b5.
+  Bronstein(Duration),
+}
+
+/// Part of a time control, e.g. 40 moves in 90 minutes with 30 seconds per move.
+///
+/// Parameters:
+/// `moves`: Option<u32>, moves to make in this stage, None for the rest of the game
+/// `time`: Duration, added to the clock when the stage starts
+/// `bonus`: Bonus, for every move in this stage
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stage {
+  pub moves: Option<u32>,
+  pub time: Duration,
+  pub bonus: Bonus,
+}
+
+/// The stages of a game, played in order. If the last stage has a number
+/// of moves, it starts over when they are made (e.g. 40/120 is 120 minutes
+/// for every 40 moves).
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeControl {
+  pub stages: Vec<Stage>,
+}
+
+impl TimeControl {
+  /// The stage a player is in after making this many moves.
+  fn stage(&self, moves: u32) -> &Stage {
+    let mut boundary = 0;
+
+    for stage in self.stages.iter() {
+      match stage.moves {
+        Some(count) => {
+          boundary += count;
+
+          if moves < boundary {
+            return stage;
+          }
+        },
+
+        None => return stage,
+      };
+    }
+
+    // The last stage repeats
+    self.stages.last().unwrap()
+  }
+
+  /// The stage that starts when a player has made exactly this many moves, if any.
+  fn stage_reached(&self, moves: u32) -> Option<&Stage> {
+    let mut boundary = 0;
+
+    for (i, stage) in self.stages.iter().enumerate() {
+      match stage.moves {
+        Some(count) => {
+          boundary += count;
+
+          if moves == boundary {
+            return Some(self.stages.get(i + 1).unwrap_or(stage));
+          }
+
+          else if moves < boundary {
+            return None;
+          }
+        },
+
+        None => return None,
+      };
+    }
+
+    // Past the end, the last stage repeats
+    let last = self.stages.last().unwrap();
+
+    match last.moves {
+      Some(count) if (moves - boundary) % count == 0 => Some(last),
+      _ => None,
+    }
+  }
+}
+
+impl FromStr for TimeControl {
+  type Err = ChessError;
+
+  /// Stages separated by commas, each [moves/]minutes[+increment|dDelay|bDelay]
+  /// with the bonus in seconds, e.g. 5, 3+2, 15d5, 40/90+30,30+30.
+  fn from_str(s: &str) -> Result<TimeControl, ChessError> {
+    lazy_static! {
+      static ref RE: Regex = Regex::new(r"^(?:([0-9]+)/)?([0-9]+)(?:([+db])([0-9]+))?$").unwrap();
+    }
+
+    let bad = || ChessError::Parse(format!("'{}' is not a time control, e.g. 5+3, 90 or 40/90+30,30+30.", s));
+
+    let mut stages = Vec::new();
+
+    for part in s.trim().split(',') {
+      let captures = RE.captures(part.trim()).ok_or_else(bad)?;
+
+      let moves = match captures.get(1) {
+        Some(moves) => Some(moves.as_str().parse::<u32>().map_err(|_| bad())?),
+        None => None,
+      };
+
+      let minutes = captures[2].parse::<u64>().map_err(|_| bad())?;
+
+      let bonus = match (captures.get(3), captures.get(4)) {
+        (Some(kind), Some(seconds)) => {
+          let seconds = Duration::from_secs(seconds.as_str().parse::<u64>().map_err(|_| bad())?);
+
+          match kind.as_str() {
+            "+" => Bonus::Increment(seconds),
+            "d" => Bonus::Delay(seconds),
+            _ => Bonus::Bronstein(seconds),
+          }
+        },
+
+        _ => Bonus::None,
+      };
+
+      if moves == Some(0) || (minutes == 0 && bonus == Bonus::None) {
+        return Err(bad());
+      }
+
+      stages.push(Stage{moves, time: Duration::from_secs(minutes * 60), bonus});
+    }
+
+    Ok(TimeControl{stages})
+  }
+}
+
+impl fmt::Display for TimeControl {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let stages: Vec<String> = self.stages.iter().map(|stage| {
+      let moves = match stage.moves {
+        Some(moves) => format!("{}/", moves),
+        None => String::new(),
+      };
+
+      let bonus = match stage.bonus {
+        Bonus::None => String::new(),
+        Bonus::Increment(seconds) => format!("+{}", seconds.as_secs()),
+        Bonus::Delay(seconds) => format!("d{}", seconds.as_secs()),
+        Bonus::Bronstein(seconds) => format!("b{}", seconds.as_secs()),
+      };
+
+      format!("{}{}{}", moves, stage.time.as_secs() / 60, bonus)
+    }).collect();
+
+    write!(f, "{}", stages.join(","))
+  }
+}
+
+/// A clock for both players. Only the side to move's time runs.
+///
+/// Parameters:
+/// `control`: TimeControl
+/// `remaining`: [Duration; 2], time left for white and black, not counting the running move
+/// `moves`: [u32; 2], moves made by white and black on this clock
+/// `running`: Option<(Color, Instant)>, whose time runs and since when
+/// `flagged`: Option<Color>, who ran out of time
+#[derive(Clone, Debug)]
+pub struct Clock {
+  control: TimeControl,
+  remaining: [Duration; 2],
+  moves: [u32; 2],
+  running: Option<(Color, Instant)>,
+  flagged: Option<Color>,
+}
+
+impl Clock {
+  /// Create a stopped clock with the time of the first stage on both sides.
+  ///
+  /// Parameters:
+  /// `control`: TimeControl
+  ///
+  /// Return: Clock
+  pub fn new(control: TimeControl) -> Clock {
+    let time = control.stages[0].time;
+
+    Clock{
+      control,
+      remaining: [time, time],
+      moves: [0, 0],
+      running: None,
+      flagged: None,
+    }
+  }
+
+  /// The time control the clock was set to.
+  pub fn time_control(&self) -> &TimeControl {
+    &self.control
+  }
+
+  /// Start the clock of a player, e.g. white's at the start of the game.
+  ///
+  /// Parameters:
+  /// `color`: &Color
+  pub fn start(&mut self, color: &Color) {
+    if self.flagged.is_none() {
+      self.running = Some((color.clone(), Instant::now()));
+    }
+  }
+
+  /// Stop the clock, e.g. when the game is over. The running move is charged without a bonus.
+  pub fn stop(&mut self) {
+    if let Some((color, since)) = self.running.take() {
+      let i = Self::side(&color);
+      let used = self.used(&color, since.elapsed());
+
+      if used >= self.remaining[i] {
+        self.remaining[i] = Duration::from_secs(0);
+        self.flagged = Some(color);
+      }
+
+      else {
+        self.remaining[i] -= used;
+      }
+    }
+  }
+
+  /// A player made their move: charge them the time it took and start the other clock.
+  ///
+  /// Parameters:
+  /// `mover`: &Color, who made the move
+  pub fn press(&mut self, mover: &Color) {
+    let elapsed = match self.running {
+      Some((ref color, since)) if color == mover => since.elapsed(),
+      _ => Duration::from_secs(0),
+    };
+
+    self.press_after(mover, elapsed);
+  }
+
+  /// A player made their move and it took them this long.
+  ///
+  /// Parameters:
+  /// `mover`: &Color, who made the move
+  /// `elapsed`: Duration, how long the move took
+  pub fn press_after(&mut self, mover: &Color, elapsed: Duration) {
+    if self.flagged.is_some() {
+      return;
+    }
+
+    let i = Self::side(mover);
+    let stage = *self.control.stage(self.moves[i]);
+    let used = self.used(mover, elapsed);
+
+    // Out of time before the move was made
+    if used >= self.remaining[i] {
+      self.remaining[i] = Duration::from_secs(0);
+      self.flagged = Some(mover.clone());
+      self.running = None;
+      return;
+    }
+
+    self.remaining[i] -= used;
+
+    match stage.bonus {
+      Bonus::Increment(increment) => self.remaining[i] += increment,
+      Bonus::Bronstein(delay) => self.remaining[i] += elapsed.min(delay),
+      _ => (),
+    };
+
+    self.moves[i] += 1;
+
+    // Time control reached, e.g. after move 40
+    if let Some(next) = self.control.stage_reached(self.moves[i]) {
+      self.remaining[i] += next.time;
+    }
+
+    self.running = Some((mover.opposite(), Instant::now()));
+  }
+
+  /// Time left for a player, counting the move they are thinking about.
+  ///
+  /// Parameters:
+  /// `color`: &Color
+  ///
+  /// Return: Duration
+  pub fn remaining(&self, color: &Color) -> Duration {
+    let i = Self::side(color);
+
+    match self.running {
+      Some((ref running, since)) if running == color => {
+        self.remaining[i].checked_sub(self.used(color, since.elapsed())).unwrap_or_default()
+      },
+
+      _ => self.remaining[i],
+    }
+  }
+
+  /// Moves a player made on this clock.
+  pub fn moves(&self, color: &Color) -> u32 {
+    self.moves[Self::side(color)]
+  }
+
+  /// Set the time left for a player who isn't moving, e.g. to what the other player's clock says.
+  ///
+  /// Parameters:
+  /// `color`: &Color
+  /// `remaining`: Duration
+  pub fn set_remaining(&mut self, color: &Color, remaining: Duration) {
+    self.remaining[Self::side(color)] = remaining;
+  }
+
+  /// Set the number of moves a player made, e.g. when loading a game.
+  ///
+  /// Parameters:
+  /// `color`: &Color
+  /// `moves`: u32
+  pub fn set_moves(&mut self, color: &Color, moves: u32) {
+    self.moves[Self::side(color)] = moves;
+  }
+
+  /// Is somebody's time running?
+  pub fn is_running(&self) -> bool {
+    self.running.is_some()
+  }
+
+  /// The player who ran out of time, if anyone did.
+  ///
+  /// Return: Option<Color>
+  pub fn flagged(&self) -> Option<Color> {
+    if self.flagged.is_some() {
+      return self.flagged.clone();
+    }
+
+    match self.running {
+      Some((ref color, _)) if self.remaining(color) == Duration::from_secs(0) => Some(color.clone()),
+      _ => None,
+    }
+  }
+
+  /// Time charged for a move that took this long, after the delay.
+  fn used(&self, color: &Color, elapsed: Duration) -> Duration {
+    let stage = self.control.stage(self.moves[Self::side(color)]);
+
+    match stage.bonus {
+      Bonus::Delay(delay) => elapsed.checked_sub(delay).unwrap_or_default(),
+      _ => elapsed,
+    }
+  }
+
+  /// Index of a player in remaining and moves.
+  fn side(color: &Color) -> usize {
+    match *color {
+      Color::Black => 1,
+      _ => 0,
+    }
+  }
+}
+
+impl fmt::Display for Clock {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "White {} - Black {}", format_time(self.remaining(&Color::White)), format_time(self.remaining(&Color::Black)))
+  }
+}
+
+/// Time as a clock shows it: 1:30:00, 4:05, or 0:09.5 when it's nearly out.
+fn format_time(time: Duration) -> String {
+  let seconds = time.as_secs();
+
+  if seconds >= 3600 {
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+  }
+
+  else if seconds >= 10 {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+  }
+
+  else {
+    format!("0:{:02}.{}", seconds, time.subsec_millis() / 100)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn seconds(seconds: u64) -> Duration {
+    Duration::from_secs(seconds)
+  }
+
+  #[test]
+  fn test_time_control() {
+    let control = "40/90+30,30+30".parse::<TimeControl>().unwrap();
+
+    assert_eq!(control.stages.len(), 2);
+    assert_eq!(control.stages[0], Stage{moves: Some(40), time: seconds(5400), bonus: Bonus::Increment(seconds(30))});
+    assert_eq!(control.stages[1].moves, None);
+    assert_eq!(control.to_string(), "40/90+30,30+30");
+
+    assert_eq!("5".parse::<TimeControl>().unwrap().stages[0].bonus, Bonus::None);
+    assert_eq!("15d5".parse::<TimeControl>().unwrap().stages[0].bonus, Bonus::Delay(seconds(5)));
+    assert_eq!("15b5".parse::<TimeControl>().unwrap().stages[0].bonus, Bonus::Bronstein(seconds(5)));
+    assert_eq!("0+2".parse::<TimeControl>().unwrap().to_string(), "0+2");
+
+    for bad in ["", "five", "5+", "0/90", "0", "40/90,", "5x3"].iter() {
+      assert!(bad.parse::<TimeControl>().is_err(), "{}", bad);
+    }
+  }
+
+  #[test]
+  fn test_sudden_death() {
+    let mut clock = Clock::new("5".parse().unwrap());
+
+    // The clock of the side that just moved is stopped
+    clock.press_after(&Color::White, seconds(10));
+    assert_eq!(clock.remaining(&Color::White), seconds(290));
+
+    clock.press_after(&Color::Black, seconds(20));
+    assert_eq!(clock.remaining(&Color::Black), seconds(280));
+    assert_eq!(clock.flagged(), None);
+
+    // Black's move came too late
+    clock.press_after(&Color::White, seconds(1));
+    clock.press_after(&Color::Black, seconds(280));
+
+    assert_eq!(clock.flagged(), Some(Color::Black));
+    assert_eq!(clock.remaining(&Color::Black), seconds(0));
+    assert_eq!(clock.moves(&Color::Black), 1);
+  }
+
+  #[test]
+  fn test_bonus() {
+    // Fischer: the increment is added after the move
+    let mut clock = Clock::new("3+2".parse().unwrap());
+    clock.press_after(&Color::White, seconds(1));
+    assert_eq!(clock.remaining(&Color::White), seconds(181));
+
+    // Simple delay: the first seconds are free, nothing is added
+    let mut clock = Clock::new("3d5".parse().unwrap());
+    clock.press_after(&Color::White, seconds(3));
+    assert_eq!(clock.remaining(&Color::White), seconds(180));
+    clock.press_after(&Color::Black, seconds(8));
+    assert_eq!(clock.remaining(&Color::Black), seconds(177));
+
+    // Bronstein: the time used is given back, up to the delay
+    let mut clock = Clock::new("3b5".parse().unwrap());
+    clock.press_after(&Color::White, seconds(3));
+    assert_eq!(clock.remaining(&Color::White), seconds(180));
+    clock.press_after(&Color::Black, seconds(8));
+    assert_eq!(clock.remaining(&Color::Black), seconds(177));
+  }
+
+  #[test]
+  fn test_stages() {
+    let mut clock = Clock::new("2/10+30,1/5,5".parse().unwrap());
+
+    // Move 1 of the first stage
+    clock.press_after(&Color::White, seconds(60));
+    assert_eq!(clock.remaining(&Color::White), seconds(570));
+
+    // Move 2 reaches the control, the second stage adds 5 minutes
+    clock.press_after(&Color::White, seconds(60));
+    assert_eq!(clock.remaining(&Color::White), seconds(840));
+
+    // No increment in the second stage, the third stage adds 5 minutes
+    clock.press_after(&Color::White, seconds(40));
+    assert_eq!(clock.remaining(&Color::White), seconds(1100));
+
+    // The last stage is the rest of the game
+    clock.press_after(&Color::White, seconds(100));
+    assert_eq!(clock.remaining(&Color::White), seconds(1000));
+
+    // Black's clock is its own, it only just started running
+    assert!(clock.remaining(&Color::Black) > seconds(599));
+
+    // A repeating last stage, 20 minutes every 2 moves
+    let mut clock = Clock::new("2/20".parse().unwrap());
+
+    for _ in 0..4 {
+      clock.press_after(&Color::Black, seconds(60));
+    }
+
+    assert_eq!(clock.remaining(&Color::Black), seconds(3360));
+  }
+
+  #[test]
+  fn test_running() {
+    let mut clock = Clock::new("1".parse().unwrap());
+
+    clock.start(&Color::White);
+    assert_eq!(clock.flagged(), None);
+
+    // The other side's time doesn't run
+    clock.set_remaining(&Color::Black, seconds(0));
+    assert_eq!(clock.flagged(), None);
+
+    // Pressing switches the clock, black has no time left
+    clock.press(&Color::White);
+    assert_eq!(clock.flagged(), Some(Color::Black));
+    assert!(clock.remaining(&Color::White) > seconds(59));
+
+    assert_eq!(format_time(seconds(5400)), "1:30:00");
+    assert_eq!(format_time(seconds(245)), "4:05");
+    assert_eq!(format_time(Duration::from_millis(9500)), "0:09.5");
+  }
+}
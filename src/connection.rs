@@ -5,7 +5,7 @@ use serde_json::value::Value as JsonValue;
 
 // Networking
 use std::{net, thread, time};
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 
 // String
 use std::str;
@@ -14,10 +14,18 @@ use std::str;
 use std::fmt;
 
 // Input
-use helpers::input;
+use helpers::read_input;
+
+// Regex
+use regex::Regex;
 
 // Protocol
-use protocol::Message;
+use protocol::{Message, MessageRegex};
+
+// Engine
+use board::{Board, Color, Move};
+use engine;
+use san;
 
 // Retry attempts for http connection
 // const RETRY_ATTEMPTS_HTTP: i32 = 5;
@@ -27,7 +35,7 @@ use protocol::Message;
 pub trait Connection: Send {
   fn send_message(&mut self, message: &str) -> bool;
   fn wait_for_message(&mut self) -> Result<String, String>;
-  fn get_message(&mut self) -> Result<String, String>;
+  fn get_message(&mut self) -> Result<Option<String>, String>;
 }
 
 /// Echo connection
@@ -55,14 +63,19 @@ impl Connection for EchoConnection {
   }
 
   ///
-  fn get_message(&mut self) -> Result<String, String> {
-    Ok(String::from("Nothing"))
+  fn get_message(&mut self) -> Result<Option<String>, String> {
+    Ok(None)
   }
 }
 
 /// Tcp connection
+///
+/// Parameters:
+/// `stream`: net::TcpStream
+/// `received`: String, read from the stream but not handed out yet; may end in half a message
 pub struct TcpConnection {
   stream: net::TcpStream,
+  received: String,
 }
 
 enum TcpConnectionDelimiter {
@@ -86,7 +99,7 @@ impl TcpConnection {
     // Attempt a connection to server
     match net::TcpStream::connect(host) {
       Ok(stream) => {
-        let connection = TcpConnection{stream};
+        let connection = TcpConnection{stream, received: String::new()};
         Ok(connection)
       },
 
@@ -102,7 +115,7 @@ impl TcpConnection {
     // Create a listening socket
     let listener = match net::TcpListener::bind(host) {
       Ok(listener) => listener,
-      Err(err) => panic!("Connection > Host could not bind to address: {}, {}", host, err),
+      Err(err) => return Err(format!("Could not bind to address: {}, {}", host, err)),
     };
 
     // Wait for the other player to connect
@@ -110,12 +123,58 @@ impl TcpConnection {
 
     let (stream, addr) = match listener.accept() {
       Ok((stream, addr)) => (stream, addr),
-      Err(err) => panic!("TcpConnection > Host could not accept: {}", err),
+      Err(err) => return Err(format!("Could not accept the other player: {}", err)),
     };
 
     println!("Client connected from: {}", addr);
 
-    Ok(TcpConnection{stream})
+    Ok(TcpConnection{stream, received: String::new()})
+  }
+
+  /// Take the next whole message out of what was received.
+  fn next_message(&mut self) -> Option<String> {
+    let delimiter = TcpConnectionDelimiter::EndOfMessage.to_string();
+    let end = self.received.find(&delimiter)?;
+
+    let message = self.received[..end].replace('\0', "");
+    self.received.drain(..end + delimiter.len());
+
+    Some(message)
+  }
+
+  /// Read what arrived on the stream. Several messages can arrive in one read,
+  /// or a message in several reads.
+  ///
+  /// Parameters:
+  /// `blocking`: bool, wait for something to arrive
+  ///
+  /// Return: Result<(), String> (an error if the connection broke or was closed)
+  fn receive(&mut self, blocking: bool) -> Result<(), String> {
+    let mut buffer = [0u8; 512];
+
+    if let Err(err) = self.stream.set_nonblocking(!blocking) {
+      return Err(format!("Connection error: {}", err));
+    }
+
+    match self.stream.read(&mut buffer) {
+      Ok(0) => Err(String::from("Connection closed.")),
+
+      Ok(size) => {
+        match str::from_utf8(&buffer[..size]) {
+          Ok(data) => self.received.push_str(data),
+          Err(err) => return Err(format!("Decoding error: {}", err)),
+        };
+
+        println!("TcpConnection > Got message: {}", String::from_utf8_lossy(&buffer[..size]));
+
+        Ok(())
+      },
+
+      // Nothing arrived yet
+      Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(()),
+
+      Err(err) => Err(format!("Connection error: {}", err)),
+    }
   }
 }
 
@@ -127,11 +186,14 @@ impl Connection for TcpConnection {
     // Append the end of message delimiter
     data.push_str(&TcpConnectionDelimiter::EndOfMessage.to_string());
 
-    let mut raw_data = data.as_bytes();
+    let raw_data = data.as_bytes();
 
-    println!("TcpConnection > Sending {}", str::from_utf8(&raw_data).unwrap());
-    
-    match self.stream.write(&mut raw_data) {
+    println!("TcpConnection > Sending {}", String::from_utf8_lossy(&raw_data));
+
+    // get_message() may have left the stream non-blocking
+    let _ = self.stream.set_nonblocking(false);
+
+    match self.stream.write_all(raw_data) {
       Ok(_) => true,
       Err(err) => {
         println!("TcpConnection > Writing error: {}", err);
@@ -142,39 +204,29 @@ impl Connection for TcpConnection {
 
   /// Receive a message from another player
   fn wait_for_message(&mut self) -> Result<String, String> {
-    let mut message = String::new();
-
-    while !message.contains(&TcpConnectionDelimiter::EndOfMessage.to_string()) {
-      let mut buffer = [0u8; 512];
-      
-      match self.stream.read(&mut buffer) {
-        Ok(_) => (),
-        Err(err) => panic!("Connection error: {}", err),
-      };
-
-      match str::from_utf8(&buffer) {
-        Ok(data) => message.push_str(&data),
-        Err(err) => panic!("Decoding error: {}", err),
-      };
-
-      println!("TcpConnection > Got message: {}", String::from_utf8_lossy(&buffer));
+    loop {
+      if let Some(message) = self.next_message() {
+        return Ok(message);
+      }
 
-      // If we receive an empty message, usually the connection is terminated.
-      if buffer[0] as char == '\0' {
-        break;
+      // The other player is gone, it's like they said bye
+      if self.receive(true).is_err() {
+        return Ok(Message::Bye.to_string());
       }
     }
+  }
 
-    let message = message
-      .replace('\0', "")
-      .replace(&TcpConnectionDelimiter::EndOfMessage.to_string(), "");
+  /// Receive a message from another player if one arrived (non-blocking)
+  fn get_message(&mut self) -> Result<Option<String>, String> {
+    if let Some(message) = self.next_message() {
+      return Ok(Some(message));
+    }
 
-    Ok(message)
-  }
+    if self.receive(false).is_err() {
+      return Ok(Some(Message::Bye.to_string()));
+    }
 
-  ///
-  fn get_message(&mut self) -> Result<String, String> {
-    Ok(String::from(""))
+    Ok(self.next_message())
   }
 }
 
@@ -200,23 +252,135 @@ impl Connection for SelfConnection {
   fn wait_for_message(&mut self) -> Result<String, String> {
     print!(" Other player > ");
 
-    let value = input();
+    lazy_static! {
+      static ref MAKE_MOVE: Regex = Regex::new(&MessageRegex::MakeMove.to_string()).unwrap();
+    }
+
+    // Nothing left to read, e.g. the moves were piped in
+    let value = match read_input() {
+      Some(value) => value,
+      None => return Ok(Message::Bye.to_string()),
+    };
+
+    let message = format!("{} {}", Message::MakeMove, value);
 
     if value == "exit" {
       Ok(Message::Bye.to_string())
     }
 
-    else if value.len() != 4 {
+    else if value == "undo" {
+      Ok(Message::Undo.to_string())
+    }
+
+    else if !MAKE_MOVE.is_match(&message) {
       Ok(format!("{}", Message::BadMessage))
     }
     
     else {
-      Ok(format!("{} {}", Message::MakeMove, value))
+      Ok(message)
     }
   }
 
-  fn get_message(&mut self) -> Result<String, String> {
-    self.wait_for_message()
+  fn get_message(&mut self) -> Result<Option<String>, String> {
+    self.wait_for_message().map(Some)
+  }
+}
+
+/// Computer player, see the engine module.
+/// It keeps its own board, following the moves sent to it.
+///
+/// Parameters:
+/// `board`: Board, the game as the engine sees it
+/// `positions`: Vec<Board>, the board before every move, for undo
+/// `depth`: usize, plies to look ahead
+pub struct EngineConnection {
+  board: Board,
+  positions: Vec<Board>,
+  depth: usize,
+}
+
+impl EngineConnection {
+  /// Create an engine for a game starting in this position.
+  ///
+  /// Parameters:
+  /// `fen`: &str
+  ///
+  /// Return: Result<EngineConnection, String>
+  pub fn new(fen: &str) -> Result<EngineConnection, String> {
+    match Board::from_fen(fen, Color::Nil) {
+      Ok(board) => Ok(EngineConnection{board, positions: Vec::new(), depth: engine::DEFAULT_DEPTH}),
+      Err(err) => Err(err.to_string()),
+    }
+  }
+
+  /// Make a move on the engine's board.
+  fn play(&mut self, m: &Move) -> bool {
+    let before = self.board.clone();
+
+    match self.board.play(m, true) {
+      Ok(_) => {
+        self.positions.push(before);
+        true
+      },
+
+      Err(_) => false,
+    }
+  }
+}
+
+impl Connection for EngineConnection {
+  /// Follow the other player's moves and take backs
+  fn send_message(&mut self, message: &str) -> bool {
+    let undo = Message::Undo.to_string();
+    let make_move = format!("{} ", Message::MakeMove);
+
+    if message == undo {
+      // Their last move and our reply
+      for _ in 0..2 {
+        if let Some(board) = self.positions.pop() {
+          self.board = board;
+        }
+      }
+
+      true
+    }
+
+    else if message.starts_with(&make_move) {
+      let notation = &message[make_move.len()..];
+
+      let m = match notation.parse::<Move>() {
+        Ok(m) => m,
+        Err(_) => match san::parse_san(&self.board, notation) {
+          Ok(m) => m,
+          Err(_) => return false,
+        },
+      };
+
+      self.play(&m)
+    }
+
+    // Hello, clock and bye don't change the board
+    else {
+      true
+    }
+  }
+
+  /// Think of a move and play it
+  fn wait_for_message(&mut self) -> Result<String, String> {
+    match engine::best_move(&self.board, self.depth) {
+      Some(m) => {
+        self.play(&m);
+
+        Ok(format!("{} {}", Message::MakeMove, m))
+      },
+
+      // Nothing to play, the game is over
+      None => Ok(Message::Bye.to_string()),
+    }
+  }
+
+  fn get_message(&mut self) -> Result<Option<String>, String> {
+    self.wait_for_message().map(Some)
   }
 }
 
@@ -241,11 +405,14 @@ impl HttpConnection {
     });
 
     let location = match client.post(&join_url).json(&body).send() {
-      Ok(mut res) => {
+      Ok(res) => {
         let headers = res.headers().clone();
         println!("{:?}", headers.clone());
-        let location_header = String::from(headers[header::LOCATION].to_str().unwrap());
-        location_header
+
+        match headers.get(header::LOCATION).and_then(|location| location.to_str().ok()) {
+          Some(location) => String::from(location),
+          None => return Err(String::from("Server didn't say where our client is.")),
+        }
       }
       Err(err) => return Err(err.to_string()),
     };
@@ -268,45 +435,43 @@ impl Connection for HttpConnection {
     println!("Sending http message: {}", message);
     let endpoint = format!("{}/{}/message", self.endpoint, self.location);
     println!("Endpoint: {}", endpoint);
-    self.client.post(&endpoint)
-      .json(&json!({"message": message}))
-      .send()
-    .unwrap();
-
-      true
+    match self.client.post(&endpoint).json(&json!({"message": message})).send() {
+      Ok(response) => !response.status().is_client_error() && !response.status().is_server_error(),
+      Err(err) => {
+        println!("Could not send the message: {}", err);
+        false
+      },
+    }
   }
 
   fn wait_for_message(&mut self) -> Result<String, String> {
     // let mut attempts = RETRY_ATTEMPTS_HTTP;
 
     loop {
-      let mut response = self.client
-        .get(&format!("{}/{}", self.endpoint, self.other_player))
-        .send()
-      .unwrap();
+      let mut response = match self.client.get(&format!("{}/{}", self.endpoint, self.other_player)).send() {
+        Ok(response) => response,
+        Err(err) => return Err(format!("Could not reach the server: {}", err)),
+      };
 
       if response.status().is_server_error() {
         println!("Server error: {}", response.status());
       }
 
       if response.status().is_client_error() {
-        panic!("Client error: {}", response.status());
+        return Err(format!("Client error: {}", response.status()));
       }
 
       let client: JsonValue = match response.json() {
         Ok(client) => client,
-        Err(err) => panic!("Bad JSON from server: {}", err),
+        Err(err) => return Err(format!("Bad JSON from server: {}", err)),
       };
 
       println!("Client: {}", client);
 
-      let message = String::from(
-        client["nextMessage"]
-        .as_object()
-        .unwrap()["message"]
-        .as_str()
-        .unwrap()
-      );
+      let message = match client["nextMessage"]["message"].as_str() {
+        Some(message) => String::from(message),
+        None => return Err(format!("Server sent no message: {}", client)),
+      };
 
       if message != self.previous_message {
         self.previous_message = message.clone();
@@ -319,14 +484,17 @@ impl Connection for HttpConnection {
     }
   }
 
-  fn get_message(&mut self) -> Result<String, String> {
-    Ok(String::from("Nothing"))
+  fn get_message(&mut self) -> Result<Option<String>, String> {
+    self.wait_for_message().map(Some)
   }
 }
 
 impl Drop for HttpConnection {
   fn drop(&mut self) {
-    self.client.delete(&format!("{}/clients/{}", self.endpoint, self.name)).send().unwrap();
+    // Leaving anyway, the server forgets us eventually
+    if let Err(err) = self.client.delete(&format!("{}/clients/{}", self.endpoint, self.name)).send() {
+      println!("Could not leave the server: {}", err);
+    }
   }
 }
 
@@ -0,0 +1,223 @@
+/// Set up a position by hand before playing it, e.g. for a puzzle.
+
+use std::fmt;
+use std::str::FromStr;
+
+use board::{Board, Color, Piece, Square};
+use error::ChessError;
+use helpers;
+
+/// How to use the editor in the terminal.
+pub const HELP: &str = "Set up the position, then type done to play it.
+  Ke1, pd7      Put a piece on a square (uppercase is white, lowercase black)
+  -e1           Take the piece off a square
+  clear         Take every piece off the board
+  start         The usual starting position
+  turn w|b      The side to move
+  castling KQkq Who can still castle, or - for nobody
+  fen <fen>     Start from a FEN
+  done          Check the position and play it
+  exit          Leave without playing";
+
+/// One change to the position, as typed in the terminal.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+  Place(Square, Piece),
+  Remove(Square),
+  Clear,
+  Start,
+  Turn(Color),
+  Castling(String),
+  Fen(String),
+  Done,
+  Exit,
+}
+
+// e.g. Ke1, -e1 or turn b
+impl FromStr for Edit {
+  type Err = ChessError;
+
+  fn from_str(command: &str) -> Result<Self, Self::Err> {
+    let command = command.trim();
+    let (word, rest) = match command.find(' ') {
+      Some(space) => (&command[..space], command[space + 1..].trim()),
+      None => (command, ""),
+    };
+
+    match (word, rest) {
+      ("clear", "") => Ok(Edit::Clear),
+      ("start", "") => Ok(Edit::Start),
+      ("done", "") => Ok(Edit::Done),
+      ("exit", "") => Ok(Edit::Exit),
+
+      ("turn", "w") | ("turn", "white") => Ok(Edit::Turn(Color::White)),
+      ("turn", "b") | ("turn", "black") => Ok(Edit::Turn(Color::Black)),
+
+      ("castling", rights) if !rights.is_empty() => Ok(Edit::Castling(String::from(rights))),
+      ("fen", fen) if !fen.is_empty() => Ok(Edit::Fen(String::from(fen))),
+
+      (word, "") if word.starts_with('-') => Ok(Edit::Remove(word[1..].parse::<Square>()?)),
+
+      (word, "") if word.len() == 3 && word.is_char_boundary(1) => {
+        let piece = word[..1].parse::<Piece>()?;
+
+        Ok(Edit::Place(word[1..].parse::<Square>()?, piece))
+      },
+
+      _ => Err(ChessError::BadNotation(format!("'{}' is not an edit, type help to see them.", command))),
+    }
+  }
+}
+
+impl fmt::Display for Edit {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Edit::Place(square, ref piece) => write!(f, "{}{}", piece, square),
+      Edit::Remove(square) => write!(f, "-{}", square),
+      Edit::Clear => write!(f, "clear"),
+      Edit::Start => write!(f, "start"),
+      Edit::Turn(ref color) => write!(f, "turn {}", color.to_string().to_lowercase()),
+      Edit::Castling(ref rights) => write!(f, "castling {}", rights),
+      Edit::Fen(ref fen) => write!(f, "fen {}", fen),
+      Edit::Done => write!(f, "done"),
+      Edit::Exit => write!(f, "exit"),
+    }
+  }
+}
+
+/// Make a change to the position. Done and exit are up to whoever is editing.
+///
+/// Parameters:
+/// `board`: &mut Board
+/// `edit`: &Edit
+///
+/// Return: Result<(), ChessError> (a bad castling right or FEN; the board doesn't change then)
+pub fn apply(board: &mut Board, edit: &Edit) -> Result<(), ChessError> {
+  match *edit {
+    Edit::Place(square, ref piece) => board.place(square, piece.clone()),
+    Edit::Remove(square) => { board.remove(square); },
+    Edit::Clear => board.clear(),
+    Edit::Start => *board = Board::new(board.get_my_color()),
+    Edit::Turn(ref color) => board.set_turn(color.clone()),
+    Edit::Castling(ref rights) => board.set_castling(rights)?,
+    Edit::Fen(ref fen) => *board = Board::from_fen(fen, board.get_my_color())?,
+    Edit::Done | Edit::Exit => (),
+  };
+
+  Ok(())
+}
+
+/// Every castling right the kings and rooks still allow, e.g. for an editor
+/// without a way to choose them.
+///
+/// Return: String (a mix of KQkq, or - for nobody)
+pub fn possible_castling(board: &Board) -> String {
+  let rights: String = "KQkq".chars()
+    .filter(|right| board.clone().set_castling(&right.to_string()).is_ok())
+    .collect();
+
+  match rights.is_empty() {
+    true => String::from("-"),
+    false => rights,
+  }
+}
+
+/// Set up a position in the terminal, typing edits until it's done.
+///
+/// Parameters:
+/// `fen`: &str, the position to start from
+/// `my_color`: Color of the local player
+///
+/// Return: Option<Board> (the position to play, None if we left)
+pub fn run(fen: &str, my_color: Color) -> Option<Board> {
+  let mut board = Board::from_fen(fen, my_color.clone()).unwrap_or(Board::new(my_color));
+
+  println!("\r\n{}\r", HELP);
+
+  loop {
+    println!("\n\r{}\n\r", board);
+    println!("{}\r", board.to_fen());
+    print!(" Edit > ");
+
+    // No more input, nothing to play
+    let command = helpers::read_input()?;
+
+    if command.trim() == "help" {
+      println!("{}\r", HELP);
+      continue;
+    }
+
+    let edit = match command.parse::<Edit>() {
+      Ok(edit) => edit,
+      Err(err) => {
+        println!("{}\r", err);
+        continue;
+      },
+    };
+
+    match edit {
+      Edit::Exit => return None,
+
+      Edit::Done => match board.validate() {
+        Ok(_) => return Some(board),
+        Err(err) => println!("{}\r", err),
+      },
+
+      edit => if let Err(err) = apply(&mut board, &edit) {
+        println!("{}\r", err);
+      },
+    };
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use board::STARTING_FEN;
+
+  #[test]
+  fn test_edits() {
+    let square = |notation: &str| notation.parse::<Square>().unwrap();
+
+    assert_eq!("Ke1".parse::<Edit>().unwrap(), Edit::Place(square("e1"), "K".parse::<Piece>().unwrap()));
+    assert_eq!("pd7".parse::<Edit>().unwrap(), Edit::Place(square("d7"), "p".parse::<Piece>().unwrap()));
+    assert_eq!("-e1".parse::<Edit>().unwrap(), Edit::Remove(square("e1")));
+    assert_eq!("turn b".parse::<Edit>().unwrap(), Edit::Turn(Color::Black));
+    assert_eq!("castling Kq".parse::<Edit>().unwrap(), Edit::Castling(String::from("Kq")));
+    assert_eq!("fen 8/8/8/8/8/8/8/K6k w - -".parse::<Edit>().unwrap(), Edit::Fen(String::from("8/8/8/8/8/8/8/K6k w - -")));
+    assert_eq!(" done ".parse::<Edit>().unwrap(), Edit::Done);
+
+    for command in ["Xe1", "Ke9", "-", "turn", "turn n", "castling", "Kée", "play"].iter() {
+      assert!(command.parse::<Edit>().is_err(), "{}", command);
+    }
+
+    // Round trip
+    for command in ["Ke1", "-h8", "turn w", "castling -", "clear", "start"].iter() {
+      assert_eq!(command.parse::<Edit>().unwrap().to_string(), *command);
+    }
+  }
+
+  #[test]
+  fn test_apply() {
+    let mut board = Board::new(Color::White);
+
+    for command in ["clear", "Ke1", "Rh1", "Ra1", "ke8", "turn b"].iter() {
+      apply(&mut board, &command.parse::<Edit>().unwrap()).unwrap();
+    }
+
+    assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/R3K2R b - - 0 1");
+    assert_eq!(possible_castling(&board), "KQ");
+
+    let rights = possible_castling(&board);
+    apply(&mut board, &Edit::Castling(rights)).unwrap();
+    assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/R3K2R b KQ - 0 1");
+
+    // A bad right leaves the board alone
+    assert!(apply(&mut board, &Edit::Castling(String::from("k"))).is_err());
+    assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/R3K2R b KQ - 0 1");
+
+    apply(&mut board, &Edit::Start).unwrap();
+    assert_eq!(board.to_fen(), STARTING_FEN);
+    assert_eq!(possible_castling(&board), "KQkq");
+  }
+}
@@ -0,0 +1,248 @@
+/// A small engine to play against: it looks a few moves ahead, counts material
+/// and likes its pawns and minor pieces in the centre.
+
+use board::{Board, Move, MoveKind, PieceKind, Square};
+
+/// How many moves (plies) the engine looks ahead.
+pub const DEFAULT_DEPTH: usize = 3;
+
+/// Worth of a checkmate, more than all the material on the board.
+const MATE: i32 = 100_000;
+
+/// The move the engine would play.
+///
+/// Parameters:
+/// `board`: &Board
+/// `depth`: usize, plies to look ahead, at least 1
+///
+/// Return: Option<Move> (None if there are no legal moves)
+pub fn best_move(board: &Board, depth: usize) -> Option<Move> {
+  search_root(board, depth).map(|(m, _)| m)
+}
+
+/// The line the engine expects, starting with its move, and how good it is.
+///
+/// Parameters:
+/// `board`: &Board
+/// `depth`: usize, plies to look ahead, at least 1
+///
+/// Return: Option<(Vec<Move>, i32)> (the moves and the score in centipawns for the side
+/// to move, see describe_score(); None if there are no legal moves)
+pub fn analyze(board: &Board, depth: usize) -> Option<(Vec<Move>, i32)> {
+  let (first, score) = search_root(board, depth)?;
+  let mut line = vec![first];
+  let mut after = board.clone();
+
+  // Each reply looks one ply less ahead, like the search did
+  for remaining in (1..depth.max(1)).rev() {
+    if after.play(line.last().unwrap(), true).is_err() {
+      break;
+    }
+
+    match best_move(&after, remaining) {
+      Some(m) => line.push(m),
+      None => break,
+    };
+  }
+
+  Some((line, score))
+}
+
+/// A score in pawns, e.g. +0.35, or who gets mated.
+///
+/// Parameters:
+/// `score`: i32, centipawns for the side to move, see analyze()
+///
+/// Return: String
+pub fn describe_score(score: i32) -> String {
+  if score > MATE {
+    String::from("mate")
+  }
+
+  else if score < -MATE {
+    String::from("mated")
+  }
+
+  else {
+    format!("{:+.2}", score as f64 / 100.0)
+  }
+}
+
+/// The best move and its score.
+fn search_root(board: &Board, depth: usize) -> Option<(Move, i32)> {
+  let mut best: Option<(Move, i32)> = None;
+  let mut alpha = -MATE - 1;
+
+  for m in ordered_moves(board) {
+    let mut after = board.clone();
+
+    if after.play(&m, true).is_err() {
+      continue;
+    }
+
+    let score = -search(&after, depth.max(1) - 1, -MATE - 1, -alpha);
+
+    // The first best move wins ties, so the engine is predictable
+    if best.as_ref().map_or(true, |&(_, best_score)| score > best_score) {
+      alpha = alpha.max(score);
+      best = Some((m, score));
+    }
+  }
+
+  best
+}
+
+/// Negamax with alpha-beta pruning.
+///
+/// Return: i32 (the score for the side to move)
+fn search(board: &Board, depth: usize, mut alpha: i32, beta: i32) -> i32 {
+  if depth == 0 {
+    return evaluate(board);
+  }
+
+  let moves = ordered_moves(board);
+
+  if moves.is_empty() {
+    // Mated sooner is worse
+    return match board.is_in_check(&board.get_turn()) {
+      true => -MATE - depth as i32,
+      false => 0,
+    };
+  }
+
+  for m in moves {
+    let mut after = board.clone();
+
+    if after.play(&m, true).is_err() {
+      continue;
+    }
+
+    let score = -search(&after, depth - 1, -beta, -alpha);
+
+    if score >= beta {
+      return beta;
+    }
+
+    alpha = alpha.max(score);
+  }
+
+  alpha
+}
+
+/// Material and the centre, from the side to move's point of view.
+fn evaluate(board: &Board) -> i32 {
+  let turn = board.get_turn();
+  let mut score = 0;
+
+  for file in 0..8 {
+    for rank in 0..8 {
+      if let Some(piece) = Square::new(file, rank).and_then(|square| board.piece_at(square)) {
+        let value = piece_value(&piece.kind) + centre_bonus(&piece.kind, file, rank);
+
+        score += match piece.color == turn {
+          true => value,
+          false => -value,
+        };
+      }
+    }
+  }
+
+  score
+}
+
+/// Legal moves, captures and promotions first, so good moves are searched early.
+fn ordered_moves(board: &Board) -> Vec<Move> {
+  let mut moves = board.legal_moves();
+
+  moves.sort_by_key(|m| {
+    let captured = match m.kind {
+      MoveKind::Capture => board.piece_at(m.to).map_or(0, |piece| piece_value(&piece.kind)),
+      MoveKind::EnPassant => piece_value(&PieceKind::Pawn),
+      _ => 0,
+    };
+
+    let promoted = m.promotion.as_ref().map_or(0, piece_value);
+
+    -(captured + promoted)
+  });
+
+  moves
+}
+
+/// A little extra for pawns, knights and bishops near the centre.
+fn centre_bonus(kind: &PieceKind, file: usize, rank: usize) -> i32 {
+  // 0 on the edge, 3 in the middle four squares
+  let from_edge = |line: usize| match line < 4 {
+    true => line,
+    false => 7 - line,
+  };
+
+  let closeness = from_edge(file).min(from_edge(rank)) as i32;
+
+  match *kind {
+    PieceKind::Pawn | PieceKind::Knight | PieceKind::Bishop => closeness * 10,
+    _ => 0,
+  }
+}
+
+/// Worth of a piece in centipawns.
+fn piece_value(kind: &PieceKind) -> i32 {
+  match *kind {
+    PieceKind::Pawn => 100,
+    PieceKind::Knight => 300,
+    PieceKind::Bishop => 320,
+    PieceKind::Rook => 500,
+    PieceKind::Queen => 900,
+    _ => 0,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use board::Color;
+
+  fn best(fen: &str) -> String {
+    let board = Board::from_fen(fen, Color::White).unwrap();
+
+    best_move(&board, DEFAULT_DEPTH).unwrap().to_string()
+  }
+
+  #[test]
+  fn test_best_move() {
+    // Mate in one
+    assert_eq!(best("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"), "a1a8");
+
+    // A free queen
+    assert_eq!(best("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1"), "d2d5");
+
+    // Not the defended pawn, the rook would be lost
+    assert_ne!(best("4k3/2p5/3p4/8/8/8/3R4/4K3 w - - 0 1"), "d2d6");
+
+    // Mated, no moves
+    let board = Board::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", Color::White).unwrap();
+    assert_eq!(best_move(&board, DEFAULT_DEPTH), None);
+  }
+
+  #[test]
+  fn test_analyze() {
+    // Mate in one, nothing comes after it
+    let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", Color::White).unwrap();
+    let (line, score) = analyze(&board, DEFAULT_DEPTH).unwrap();
+
+    assert_eq!(line.iter().map(|m| m.to_string()).collect::<Vec<String>>(), vec!["a1a8"]);
+    assert_eq!(describe_score(score), "mate");
+
+    // The queen is taken and the line goes on for every ply
+    let board = Board::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", Color::White).unwrap();
+    let (line, score) = analyze(&board, DEFAULT_DEPTH).unwrap();
+
+    assert_eq!(line.len(), DEFAULT_DEPTH);
+    assert_eq!(line[0].to_string(), "d2d5");
+    assert!(score > 400, "{}", score);
+
+    assert_eq!(describe_score(35), "+0.35");
+    assert_eq!(describe_score(-120), "-1.20");
+    assert_eq!(describe_score(-MATE - 2), "mated");
+  }
+}
@@ -0,0 +1,98 @@
+// Display trait
+use std::fmt;
+
+// Error trait and I/O errors
+use std::error::Error;
+use std::io;
+
+// Serialization
+extern crate serde_json;
+
+use board::{Move, Square};
+use game::GameStatus;
+
+/// Everything that can go wrong while playing, saving or talking to the other player.
+#[derive(Debug)]
+pub enum ChessError {
+  /// Notation that can't be read, e.g. a square like i9 or a move like e2.
+  BadNotation(String),
+
+  /// There is no piece to move on this square.
+  EmptySquare(Square),
+
+  /// The piece on this square belongs to the other player.
+  WrongOwner(Square),
+
+  /// The piece belongs to the side that just moved.
+  NotYourTurn,
+
+  /// The piece can't move like that, or can't be promoted.
+  IllegalMove(Move),
+
+  /// A pawn reaching the last row needs to know what to become.
+  MissingPromotion(Move),
+
+  /// The move would leave (or put) the own king in check.
+  KingInCheck(Move),
+
+  /// No more moves can be made.
+  GameOver(GameStatus),
+
+  /// A FEN, PGN, saved game or message from the other player that can't be read.
+  Parse(String),
+
+  /// A position set up by hand that can't be played, e.g. without a black king.
+  IllegalPosition(String),
+
+  /// A recorded game (e.g. from PGN) with a move that can't be made.
+  /// Plies count from 1, for the first move in the record.
+  Replay{ply: usize, notation: String, error: Box<ChessError>},
+
+  /// Reading or writing a file failed.
+  Io(io::Error),
+
+  /// The other player can't be reached, or broke off the conversation.
+  Connection(String),
+}
+
+impl fmt::Display for ChessError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      ChessError::BadNotation(ref reason) => write!(f, "{}", reason),
+      ChessError::EmptySquare(square) => write!(f, "There is no piece on {}.", square),
+      ChessError::WrongOwner(square) => write!(f, "You can only move your own pieces, {} isn't yours.", square),
+      ChessError::NotYourTurn => write!(f, "It's not your turn."),
+      ChessError::IllegalMove(ref m) => write!(f, "Illegal move: {}.", m),
+      ChessError::MissingPromotion(ref m) => write!(f, "Pawns reaching the last row must be promoted (e.g. {}q).", m),
+      ChessError::KingInCheck(ref m) => write!(f, "{} leaves your king in check.", m),
+      ChessError::GameOver(ref status) => write!(f, "The game is over: {}.", status),
+      ChessError::Parse(ref reason) => write!(f, "{}", reason),
+      ChessError::IllegalPosition(ref reason) => write!(f, "{}", reason),
+      ChessError::Replay{ply, ref notation, ref error} => write!(f, "Can't play {} (ply {}): {}", notation, ply, error),
+      ChessError::Io(ref err) => write!(f, "{}", err),
+      ChessError::Connection(ref reason) => write!(f, "{}", reason),
+    }
+  }
+}
+
+impl Error for ChessError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match *self {
+      ChessError::Io(ref err) => Some(err),
+      ChessError::Replay{ref error, ..} => Some(error.as_ref()),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for ChessError {
+  fn from(err: io::Error) -> Self {
+    ChessError::Io(err)
+  }
+}
+
+impl From<serde_json::Error> for ChessError {
+  fn from(err: serde_json::Error) -> Self {
+    ChessError::Parse(err.to_string())
+  }
+}
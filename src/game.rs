@@ -10,6 +10,7 @@ extern crate serde_json;
 // Display trait
 use std::fmt;
 
+use std::env;
 use std::thread;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::time::Duration;
@@ -19,10 +20,18 @@ use std::fs::File;
 use std::io::prelude::*;
 
 // Game board
-use board::Board;
+use board::{Board, Move, MoveKind, Piece, Square, STARTING_FEN};
 use board::Color;
+use error::ChessError;
+use san;
+use pgn;
+use pgn::PgnGame;
+use save::{self, Players, SaveFile, SavedClock, SavedMove};
+use clock::{Clock, TimeControl};
+use editor;
 use client::Client;
-use protocol::Message;
+use protocol::{ColorChoice, Message};
+#[cfg(feature = "gui")]
 use gui::Window;
 
 // Helpers
@@ -30,12 +39,181 @@ use helpers;
 
 const MPSC_TIMEOUT_MS: u64 = 10; 
 
+/// Where finished games are written as PGN.
+const PGN_FILE: &str = "game.pgn";
+
+/// Where the game stands for the player who is about to move.
+///
+/// The fifty-move rule and threefold repetition are draws a player has to claim;
+/// the game claims them on the players' behalf. The seventy-five-move rule,
+/// fivefold repetition and insufficient material end the game on their own.
+/// In timed games, the side to move loses when their time runs out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameStatus {
+  InProgress,
+  Check,
+  Checkmate,
+  Stalemate,
+  FiftyMoveRule,
+  SeventyFiveMoveRule,
+  ThreefoldRepetition,
+  FivefoldRepetition,
+  InsufficientMaterial,
+  TimeForfeit,
+}
+
+impl GameStatus {
+  /// The game can't continue.
+  pub fn is_over(&self) -> bool {
+    match *self {
+      GameStatus::InProgress | GameStatus::Check => false,
+      _ => true,
+    }
+  }
+
+  /// The game ended without a winner.
+  pub fn is_draw(&self) -> bool {
+    self.is_over() && *self != GameStatus::Checkmate && *self != GameStatus::TimeForfeit
+  }
+}
+
+impl fmt::Display for GameStatus {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let status = match *self {
+      GameStatus::InProgress => "In progress",
+      GameStatus::Check => "Check",
+      GameStatus::Checkmate => "Checkmate",
+      GameStatus::Stalemate => "Stalemate",
+      GameStatus::FiftyMoveRule => "Fifty-move rule",
+      GameStatus::SeventyFiveMoveRule => "Seventy-five-move rule",
+      GameStatus::ThreefoldRepetition => "Threefold repetition",
+      GameStatus::FivefoldRepetition => "Fivefold repetition",
+      GameStatus::InsufficientMaterial => "Insufficient material",
+      GameStatus::TimeForfeit => "Time forfeit",
+    };
+
+    write!(f, "{}", status)
+  }
+}
+
+/// A move that was made, with what it takes to take it back.
+///
+/// Parameters:
+/// `chess_move`: Move, as played, including its kind
+/// `san`: String, the move in algebraic notation, e.g. Nxf7+
+/// `captured`: Option<Piece>, the piece it took off the board
+/// `fen_before`: String, the position before the move
+/// `clock_before`: Option<SavedClock>, both clocks when the move was made, in timed games
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+  pub chess_move: Move,
+
+  #[serde(default)]
+  pub san: String,
+
+  pub captured: Option<Piece>,
+  pub fen_before: String,
+
+  #[serde(default)]
+  pub clock_before: Option<SavedClock>,
+}
+
+/// Who we play against.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Opponent {
+  /// Ourselves, the other side's moves are typed in the terminal.
+  Local,
+
+  /// The engine.
+  Engine,
+
+  /// Somebody who connects to this address, e.g. tcp://0.0.0.0:54345
+  Host(String),
+
+  /// Somebody at this address, e.g. tcp://127.0.0.1:54345 or http://server
+  Join(String),
+}
+
+/// How to set up a game, e.g. from the command line.
+///
+/// Parameters:
+/// `opponent`: Opponent
+/// `color`: Option<ColorChoice>, the side we want, None if we don't mind
+/// `time_control`: Option<TimeControl>, None for untimed games
+/// `fen`: Option<String>, the starting position, None for the usual one
+/// `save_path`: Option<String>, where to save the game when it ends or we leave
+/// `edit`: bool, set up the starting position in the editor first
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+  pub opponent: Opponent,
+  pub color: Option<ColorChoice>,
+  pub time_control: Option<TimeControl>,
+  pub fen: Option<String>,
+  pub save_path: Option<String>,
+  pub edit: bool,
+}
+
+impl Default for Settings {
+  fn default() -> Settings {
+    Settings{
+      opponent: Opponent::Local,
+      color: None,
+      time_control: None,
+      fen: None,
+      save_path: None,
+      edit: false,
+    }
+  }
+}
+
+/// Where the local player's moves come from.
+pub enum Input {
+  /// Moves made in the window, sent over a channel.
+  Gui(Receiver<String>),
+
+  /// Moves typed (or piped) into the terminal.
+  Terminal,
+}
+
+impl Input {
+  /// The next move or command, None if there isn't one yet.
+  /// The terminal waits for a line; when there are no more, we leave.
+  fn next(&self) -> Option<String> {
+    match *self {
+      Input::Gui(ref receiver) => receiver.recv_timeout(Duration::from_millis(MPSC_TIMEOUT_MS)).ok(),
+
+      Input::Terminal => {
+        print!(" Your move > ");
+
+        Some(helpers::read_input().unwrap_or(String::from("exit")))
+      },
+    }
+  }
+}
+
 /// Game
 ///
 /// Parameters:
 /// `board`: board::Board
+/// `positions`: Vec<u64>, the key of every position seen so far, for repetitions
+/// `history`: Vec<HistoryEntry>, the moves made so far
+/// `undone`: Vec<HistoryEntry>, moves taken back that can be redone, last undone last
+/// `white`, `black`: String, the players' names (? if unknown)
+/// `date`: String, when the game started, YYYY.MM.DD
+/// `created_at`: u64, when the game started, in seconds since 1970
+/// `pgn_path`: Option<String>, where to write the PGN when the game ends
+/// `clock`: Option<Clock>, for timed games
 pub struct Game {
   board: Board,
+  positions: Vec<u64>,
+  history: Vec<HistoryEntry>,
+  undone: Vec<HistoryEntry>,
+  white: String,
+  black: String,
+  date: String,
+  created_at: u64,
+  pgn_path: Option<String>,
+  clock: Option<Clock>,
 }
 
 impl <'a>Game {
@@ -44,9 +222,19 @@ impl <'a>Game {
   /// Return: Game
   pub fn new(my_color: Color) -> Game {
     let board = Board::new(my_color);
+    let positions = vec![board.hash()];
 
     Game{
       board,
+      positions,
+      history: Vec::new(),
+      undone: Vec::new(),
+      white: String::from("?"),
+      black: String::from("?"),
+      date: helpers::today(),
+      created_at: helpers::now(),
+      pgn_path: None,
+      clock: None,
     }
   }
 
@@ -55,326 +243,998 @@ impl <'a>Game {
   /// 
   /// Parameters:
   /// `notation`: &str (the standard chess move notation, e.g. b6e6; The piece names
-  /// are not necessarily since we know what pieces are on the board already.)
+  /// are not necessarily since we know what pieces are on the board already.
+  /// Castling is e1g1 or O-O / O-O-O. Promotions add the new piece, e.g. e7e8q.)
   ///
-  /// Return: Result<(), &'static str>
-  pub fn make_move(&mut self, notation: &str, ignore_ownership: bool) -> Result<(), &'static str> {
-    let notation = notation.to_uppercase();
-
-    if notation.len() != 4 {
-      return Err("Illegal move notation (len).");
-    }
+  /// Return: Result<(), ChessError>
+  pub fn make_move(&mut self, notation: &str, ignore_ownership: bool) -> Result<(), ChessError> {
+    self.play(notation, ignore_ownership)?;
 
-    // If used in a loop
-    lazy_static! {
-      static ref RE: Regex = Regex::new("[A-H][1-8][A-H][1-8]").unwrap();
-    }
+    // A new move, the undone ones can't be redone anymore
+    self.undone.clear();
 
-    if !RE.is_match(&notation) {
-      return Err("Illegal move notation (regex).");
-    }
+    Ok(())
+  }
 
-    else {
-      let from = &notation[0..2];
-      let to = &notation[2..4];
+  /// Make a move and remember it.
+  fn play(&mut self, notation: &str, ignore_ownership: bool) -> Result<(), ChessError> {
+    let status = self.status();
 
-      self.board.make_move(from, to, ignore_ownership)
+    if status.is_over() {
+      return Err(ChessError::GameOver(status));
     }
-  }
-
-  /// Serialize the game into JSON
-  ///
-  /// Return: String
-  fn serialize(&self) -> String {
-    self.board.serialize()
-  }
 
-  /// Deserialize the game from JSON
-  ///
-  /// Parameters:
-  /// `serialized`: &str, JSON string
-  fn deserialize(&mut self, serialized: &str) {
-    let board = serde_json::from_str(serialized).unwrap();
+    let parsed = self.parse_move(notation)?;
+    let before = self.board.clone();
 
-    self.board = board;
-  }
+    self.board.play(&parsed, ignore_ownership)?;
 
-  /// Save a game
-  /// `filename`: &str
-  pub fn save(&self, filename: &str) {
-    let board = self.serialize();
+    // The board checked it, so it's one of the legal moves
+    let chess_move = before.legal_moves_from(parsed.from).into_iter()
+      .find(|m| m.to == parsed.to && m.promotion == parsed.promotion)
+      .unwrap_or(parsed);
 
-    let mut file = match File::create(filename) {
-      Ok(file) => file,
-      Err(err) => panic!("{}", err),
+    let captured = match chess_move.kind {
+      MoveKind::EnPassant => before.piece_at(Square::new(chess_move.to.file(), chess_move.from.rank()).unwrap()),
+      _ => before.piece_at(chess_move.to),
     };
 
-    file.write_all(board.as_bytes()).expect("Could not save game");
+    self.history.push(HistoryEntry{
+      san: san::to_san(&before, &chess_move),
+      chess_move,
+      captured,
+      fen_before: before.to_fen(),
+      clock_before: self.saved_clock(),
+    });
+
+    // Remember the position for repetitions
+    self.positions.push(self.board.hash());
+
+    Ok(())
   }
 
-  /// Load a game
-  /// `filename`: &str
-  pub fn load(&mut self, filename: &str) {
-    let mut file = match File::open(filename) {
-      Ok(file) => file,
-      Err(err) => panic!("{}", err),
-    };
+  /// Take back the last move, and give back the time it took.
+  ///
+  /// Return: Option<HistoryEntry> (the move taken back, None if there are no moves)
+  pub fn undo(&mut self) -> Option<HistoryEntry> {
+    let entry = self.history.pop()?;
 
-    let mut contents = String::new();
+    // The position before the move is a valid FEN, we wrote it ourselves
+    self.board = Board::from_fen(&entry.fen_before, self.board.get_my_color()).unwrap();
+    self.positions.pop();
+    self.undone.push(entry.clone());
 
-    match file.read_to_string(&mut contents) {
-      Ok(_) => (),
-      Err(err) => panic!("Game > Load Could not read to string: {}", err),
-    };
+    if let Some(ref saved) = entry.clock_before {
+      self.restore_clock(saved);
+    }
 
-    self.deserialize(&contents);
+    Some(entry)
   }
 
-  pub fn get_board(&self) -> Board {
-    return self.board.clone()
+  /// Make the last move taken back again.
+  ///
+  /// Return: Option<HistoryEntry> (the move made again, None if nothing was taken back
+  /// or it can't be played anymore, e.g. the game is over; it can still be redone later then)
+  pub fn redo(&mut self) -> Option<HistoryEntry> {
+    let entry = self.undone.pop()?;
+
+    match self.play(&entry.chess_move.to_string(), true) {
+      Ok(_) => self.history.last().cloned(),
+      Err(_) => {
+        self.undone.push(entry);
+        None
+      },
+    }
   }
 
-  /// Start the game
-  pub fn start() {
-    // Board channel is for sending the board state to the GUI
-    // and the game thread
-    let (board_sender, board_receiver): (Sender<Board>, Receiver<Board>) = channel();
-
-    // Close channel is for telling the GUI to close if the game said so
-    let (close_sender, close_receiver): (Sender<bool>, Receiver<bool>) = channel();
+  /// Take back the last move of the side to move and the reply to it,
+  /// so it's the same side's turn again.
+  ///
+  /// Return: bool (true if moves were taken back, else false)
+  fn take_back(&mut self) -> bool {
+    if self.history.len() < 2 {
+      return false;
+    }
 
-    // GUI channel is for the GUI to send us moves made through it
-    let (gui_sender, gui_receiver): (Sender<String>, Receiver<String>) = channel();
+    self.undo();
+    self.undo();
 
-    // All game logic runs in a separate thread; GUI runs in the main thread.
-    let handle = thread::spawn(move || {
-      //
-      // This is valid client selection, but let's default to self for now
-      //
+    true
+  }
 
-      // print!(" Client > ");
-      // let mut client = match helpers::input().as_ref() {
-      //   "client" => Game::build_tcp_client(),
-      //   "host" => Game::build_tcp_host(),
-      //   "self" => Client::new("self"),
-      //   "http" => Game::build_http_client(),
-      //   other => panic!("Unknown client chosen: {}", other),
-      // };
+  /// The moves made so far, first move first.
+  ///
+  /// Return: &[HistoryEntry]
+  pub fn history(&self) -> &[HistoryEntry] {
+    &self.history
+  }
 
-      let mut client = Client::new("self");
-      let mut game = Self::new(Color::White);
+  /// Parse the notation into a move on the board: coordinates (e2e4, e7e8q),
+  /// castling (O-O) or algebraic notation (Nf3, exd5, e8=Q+).
+  fn parse_move(&self, notation: &str) -> Result<Move, ChessError> {
+    let upper = notation.to_uppercase();
 
-      println!("\r\nWelcome to Rust Chess!\r\nType 'exit' to quit the game.");
+    // Castling, for whoever's turn it is
+    if let Some((from, to)) = Board::castling_to_notation(&upper, &self.board.get_turn()) {
+      return Ok(Move::new(from.parse()?, to.parse()?, None));
+    }
 
-      // Game loop
-      loop { 
+    // If used in a loop
+    lazy_static! {
+      static ref RE: Regex = Regex::new("^[A-H][1-8][A-H][1-8][QRBN]?$").unwrap();
+    }
 
-        // Print the board to the terminal (helps debugging and whatnot)
-        println!("\n\r{}\n\r", game);
+    if RE.is_match(&upper) {
+      return upper.parse();
+    }
 
-        // Initial render of the chess board
-        board_sender.send(game.get_board()).unwrap();
+    // Algebraic notation is case sensitive (b is a file, B a bishop)
+    san::parse_san(&self.board, notation)
+  }
 
-        // We are hosting
-        if client.host {
-          if game.other_player_turn(&mut client, &board_sender) {
-            close_sender.send(true).unwrap();
-            break;
-          }
+  /// Status of the game for the side about to move.
+  ///
+  /// Return: GameStatus
+  pub fn status(&self) -> GameStatus {
+    let to_move = self.board.get_turn();
+
+    let in_check = self.board.is_in_check(&to_move);
+    let can_move = self.board.has_legal_moves(&to_move);
+
+    // Checkmate wins even on the move that would draw otherwise
+    if !can_move {
+      return match in_check {
+        true => GameStatus::Checkmate,
+        false => GameStatus::Stalemate,
+      };
+    }
 
-          // Loop until a valid move is made or we exit
-          if game.my_turn(&mut client, &gui_receiver, &board_sender) {
-            close_sender.send(true).unwrap();
-            break;
-          }
-        }
+    let repetitions = self.repetitions();
+    let halfmove_clock = self.board.get_halfmove_clock();
 
-        // The other player is hosting
-        else {
-          // Loop until a valid move is made or we exit
-          if game.my_turn(&mut client, &gui_receiver, &board_sender) {
-            close_sender.send(true).unwrap();
-            break;
-          }
+    if self.board.has_insufficient_material() {
+      GameStatus::InsufficientMaterial
+    }
 
-          if game.other_player_turn(&mut client, &board_sender) {
-            close_sender.send(true).unwrap();
-            break;
-          }
-        }
-      }
-    });
+    else if repetitions >= 5 {
+      GameStatus::FivefoldRepetition
+    }
 
-    // OpenGL GUI
-    let mut window = Window::new(756, 756, gui_sender, Color::White);
+    else if halfmove_clock >= 150 {
+      GameStatus::SeventyFiveMoveRule
+    }
 
-    while !window.should_close() {
+    else if repetitions >= 3 {
+      GameStatus::ThreefoldRepetition
+    }
 
-      // Get the new board
-      match board_receiver.recv_timeout(Duration::from_millis(MPSC_TIMEOUT_MS)) {
-        Ok(new_board) => {
-          // Update board with new or old board
-          window.update_board(new_board.clone());
-          println!("New board");
-        }
-        Err(_) => (),
-      };
+    else if halfmove_clock >= 100 {
+      GameStatus::FiftyMoveRule
+    }
 
-      // Close the GUI, maybe
-      match close_receiver.recv_timeout(Duration::from_millis(MPSC_TIMEOUT_MS)) {
-        Ok(close) => {
-          if close {
-            window.close();
-            println!("Close window");
-          }
-        },
-        Err(_) => (),
-      };
+    // Only the side to move's time runs, and the position didn't end the game when their turn began
+    else if self.is_flagged() {
+      GameStatus::TimeForfeit
+    }
 
-      window.draw();
+    else if in_check {
+      GameStatus::Check
     }
 
-    // Wait for the game thread to exist
-    handle.join().unwrap();
+    else {
+      GameStatus::InProgress
+    }
   }
 
-  pub fn my_turn(&mut self, client: &mut Client, gui_receiver: &Receiver<String>, board_sender: &Sender<Board>) -> bool {
-    // Loop until a valid move is made or we exit
-    let mut should_exit = false;
-
-    let mut input = String::new();
+  /// How many times the current position has occurred in this game.
+  ///
+  /// Return: usize
+  pub fn repetitions(&self) -> usize {
+    let current = self.board.hash();
 
-    loop {
-      let mut received_input = false;
+    self.positions.iter().filter(|position| **position == current).count()
+  }
 
-      while !received_input {
-        match gui_receiver.recv_timeout(Duration::from_millis(MPSC_TIMEOUT_MS)) {
-          Ok(gui_input) => {
-            received_input = true;
-            input = gui_input;
-          },
-          Err(_) => (), // Received nothing yet
-        };
-      }
+  /// Check if the game ended and announce the result if it did.
+  ///
+  /// Return: bool (true if the game is over, else false)
+  fn is_over(&self) -> bool {
+    let status = self.status();
+    let to_move = self.board.get_turn();
+
+    match status {
+      GameStatus::Checkmate => println!("\r\nCheckmate! {} wins.\r\n", Self::color_name(&to_move.opposite())),
+      GameStatus::Stalemate => println!("\r\nStalemate! It's a draw.\r\n"),
+      GameStatus::TimeForfeit => println!("\r\n{} ran out of time. {} wins.\r\n", Self::color_name(&to_move), Self::color_name(&to_move.opposite())),
+      GameStatus::Check => println!("\r\n{} is in check.\r\n", Self::color_name(&to_move)),
+      GameStatus::InProgress => (),
+      ref draw => println!("\r\n{}! It's a draw.\r\n", draw),
+    };
 
-      if input.as_str() == "exit" {
+    status.is_over()
+  }
 
-        client.send_message(Message::Bye, "");
+  /// Print the last move in algebraic notation, e.g. 1. e4 or 1... e5
+  fn announce_last_move(&self) {
+    let entry = match self.history.last() {
+      Some(entry) => entry,
+      None => return,
+    };
 
-        should_exit = true;
-      }
+    let fen: Vec<&str> = entry.fen_before.split(' ').collect();
 
-      else {
-        // Make move
-        match self.make_move(&input, false) {
-          Ok(_) => {
-            // Tell the other player about it
-            client.send_message(Message::MakeMove, &input);
+    match fen[1] {
+      "b" => println!("{}... {}\r", fen[5], entry.san),
+      _ => println!("{}. {}\r", fen[5], entry.san),
+    };
+  }
 
-            // Print board
-            println!("\n\r{}\n\r", self);
-            break;
-          },
+  /// Human friendly color name.
+  fn color_name(color: &Color) -> &'static str {
+    match *color {
+      Color::White => "White",
+      Color::Black => "Black",
+      Color::Nil => "Nobody",
+    }
+  }
 
-          Err(err) => {
-            println!("{}", err);
-            println!("{}", input);
-            
-          }
-        };
+  /// Serialize the game into JSON, see the save module for the format.
+  ///
+  /// Return: String
+  fn serialize(&self) -> String {
+    let start_fen = match self.history.first() {
+      Some(entry) => entry.fen_before.clone(),
+      None => self.board.to_fen(),
+    };
 
-        board_sender.send(self.board.clone()).unwrap();
-        should_exit = false;
-      }
-    }
+    let saved = SaveFile{
+      version: save::SAVE_VERSION,
+      created_at: self.created_at,
+      saved_at: helpers::now(),
+      date: self.date.clone(),
+      players: Players{
+        white: self.white.clone(),
+        black: self.black.clone(),
+      },
+      my_color: self.board.get_my_color(),
+      start_fen,
+      fen: self.board.to_fen(),
+      moves: self.history.iter().map(|entry| SavedMove{
+        coordinates: entry.chess_move.to_string(),
+        san: entry.san.clone(),
+      }).collect(),
+      clock: self.saved_clock(),
+    };
 
-    should_exit
+    serde_json::to_string_pretty(&saved).unwrap()
   }
 
-  pub fn other_player_turn(&mut self, client: &mut Client, board_sender: &Sender<Board>) -> bool {
-    let mut should_exit = false;
+  /// Deserialize the game from JSON, migrating older save files.
+  ///
+  /// Parameters:
+  /// `serialized`: &str, JSON string
+  ///
+  /// Return: Result<(), ChessError> (the game is unchanged if the file is bad)
+  fn deserialize(&mut self, serialized: &str) -> Result<(), ChessError> {
+    let saved = save::read(serialized)?;
 
-    // Loop until a valid move is received
-    loop {
-      // Wait for other player to make move
-      let (msg_type, msg_payload) = match client.get_message() {
-        Ok((msg_type, msg_payload)) => (msg_type, msg_payload),
-        Err(_err) => return false,
-      };
+    let mut game = Game::new(saved.my_color.clone());
 
-      match msg_type {
+    game.load_fen(&saved.start_fen)?;
+    game.set_players(&saved.players.white, &saved.players.black);
+    game.date = saved.date.clone();
+    game.created_at = saved.created_at;
 
-        // Other player is exiting game
-        Message::Bye => { should_exit = true; break; },
+    // Replaying checks every move
+    for (i, saved_move) in saved.moves.iter().enumerate() {
+      if let Err(err) = game.make_move(&saved_move.coordinates, true) {
+        return Err(ChessError::Replay{ply: i + 1, notation: saved_move.coordinates.clone(), error: Box::new(err)});
+      }
+    }
 
-        // Other player is making a move
-        Message::MakeMove => {
-          // Make the move on our board
-          match self.make_move(&msg_payload, true) {
-            Ok(_) => { 
-              // Print board
-              println!("\n\r{}\n\r", self);
-              board_sender.send(self.board.clone()).unwrap();
-              break; 
-            },
-            
-            Err(err) => {
-              println!("{}", err);
-              println!("{}", &msg_payload);
+    if game.board.to_fen() != saved.fen {
+      return Err(ChessError::Parse(format!("The saved moves end in {}, not in the saved position {}.", game.board.to_fen(), saved.fen)));
+    }
 
-              // Tell other player bad move was made
-              client.send_message(Message::BadMessage, "");
-              continue;
-            }
-          }
-        },
+    // The clock is stopped until the game goes on
+    if let Some(ref saved_clock) = saved.clock {
+      let mut clock = Clock::new(saved_clock.time_control.parse()?);
 
-        // Unhandled; TODO: handle.
-        _ => { continue; },
-      }
+      clock.set_remaining(&Color::White, Duration::from_millis(saved_clock.white_ms));
+      clock.set_remaining(&Color::Black, Duration::from_millis(saved_clock.black_ms));
+      clock.set_moves(&Color::White, saved_clock.white_moves);
+      clock.set_moves(&Color::Black, saved_clock.black_moves);
+
+      game.clock = Some(clock);
     }
 
-    should_exit
+    game.pgn_path = self.pgn_path.take();
+    *self = game;
+
+    Ok(())
   }
 
-  /// Build the TCP Client
-  #[allow(dead_code)]
-  fn build_tcp_client() -> Client {
-    print!("Remote address > ");
+  /// Name the players for the PGN.
+  ///
+  /// Parameters:
+  /// `white`: &str
+  /// `black`: &str
+  pub fn set_players(&mut self, white: &str, black: &str) {
+    self.white = String::from(white);
+    self.black = String::from(black);
+  }
 
-    let addr = helpers::input();
+  /// Play with a clock, both sides start with the time of the first stage.
+  ///
+  /// Parameters:
+  /// `control`: TimeControl, e.g. "40/90+30,30+30".parse()
+  pub fn set_time_control(&mut self, control: TimeControl) {
+    self.clock = Some(Clock::new(control));
+  }
 
-    Client::new(&addr)
+  /// The clock, in timed games.
+  ///
+  /// Return: Option<&Clock>
+  pub fn clock(&self) -> Option<&Clock> {
+    self.clock.as_ref()
   }
 
-  /// Build the TCP host
-  #[allow(dead_code)]
-  fn build_tcp_host() -> Client {
-    print!("Local address > ");
+  /// Start the clock of the side to move.
+  fn start_clock(&mut self) {
+    let turn = self.board.get_turn();
 
-    let addr = helpers::input();
+    if let Some(ref mut clock) = self.clock {
+      clock.start(&turn);
+    }
+  }
 
-    Client::host(&addr)
+  /// Stop the clock, the game is over.
+  fn stop_clock(&mut self) {
+    if let Some(ref mut clock) = self.clock {
+      clock.stop();
+    }
   }
 
-  /// Build the HTTP client
-  #[allow(dead_code)]
-  fn build_http_client() -> Client {
-    print!("Remove address > ");
+  /// Switch the clock to the side to move after a move was made.
+  fn press_clock(&mut self) {
+    let mover = self.board.get_turn().opposite();
 
-    let addr = helpers::input();
+    if let Some(ref mut clock) = self.clock {
+      clock.press(&mover);
+    }
+  }
 
-    Client::new(&addr)
+  /// The side to move ran out of time.
+  fn is_flagged(&self) -> bool {
+    self.clock.as_ref().map_or(false, |clock| clock.flagged().is_some())
   }
-}
 
-// Display
-impl fmt::Display for Game {
+  /// Both clocks as they are now, for saving or taking back a move.
+  fn saved_clock(&self) -> Option<SavedClock> {
+    self.clock.as_ref().map(|clock| SavedClock{
+      time_control: clock.time_control().to_string(),
+      white_ms: clock.remaining(&Color::White).as_millis() as u64,
+      black_ms: clock.remaining(&Color::Black).as_millis() as u64,
+      white_moves: clock.moves(&Color::White),
+      black_moves: clock.moves(&Color::Black),
+    })
+  }
+
+  /// Set both clocks back, e.g. when a move is taken back.
+  /// A running clock goes on for the side to move.
+  ///
+  /// Parameters:
+  /// `saved`: &SavedClock, see saved_clock()
+  fn restore_clock(&mut self, saved: &SavedClock) {
+    let turn = self.board.get_turn();
+
+    if let Some(ref mut clock) = self.clock {
+      clock.set_remaining(&Color::White, Duration::from_millis(saved.white_ms));
+      clock.set_remaining(&Color::Black, Duration::from_millis(saved.black_ms));
+      clock.set_moves(&Color::White, saved.white_moves);
+      clock.set_moves(&Color::Black, saved.black_moves);
+
+      if clock.is_running() {
+        clock.start(&turn);
+      }
+    }
+  }
+
+  /// Both clocks in milliseconds, white then black, for the clock message.
+  fn clock_payload(&self) -> Option<String> {
+    self.clock.as_ref().map(|clock| format!(
+      "{} {}",
+      clock.remaining(&Color::White).as_millis(),
+      clock.remaining(&Color::Black).as_millis(),
+    ))
+  }
+
+  /// Take the other player's word for how much time they have left.
+  ///
+  /// Parameters:
+  /// `mover`: &Color, the other player, who just moved
+  /// `payload`: &str, the clock message, see clock_payload()
+  fn sync_clock(&mut self, mover: &Color, payload: &str) {
+    let times: Vec<u64> = payload.split(' ').filter_map(|ms| ms.parse().ok()).collect();
+
+    if times.len() != 2 {
+      return;
+    }
+
+    let remaining = match *mover {
+      Color::Black => times[1],
+      _ => times[0],
+    };
+
+    if let Some(ref mut clock) = self.clock {
+      clock.set_remaining(mover, Duration::from_millis(remaining));
+    }
+  }
+
+  /// Write the PGN to this file when the game ends.
+  ///
+  /// Parameters:
+  /// `path`: &str
+  pub fn set_pgn_path(&mut self, path: &str) {
+    self.pgn_path = Some(String::from(path));
+  }
+
+  /// The game in Portable Game Notation, with the Seven Tag Roster
+  /// and the moves in algebraic notation. Unfinished games end in *.
+  ///
+  /// Return: String
+  pub fn to_pgn(&self) -> String {
+    let result = pgn::result(&self.status(), &self.board.get_turn());
+
+    let tags = [
+      ("Event", String::from("Casual game")),
+      ("Site", String::from("?")),
+      ("Date", self.date.clone()),
+      ("Round", String::from("-")),
+      ("White", self.white.clone()),
+      ("Black", self.black.clone()),
+      ("Result", String::from(result)),
+    ];
+
+    let start_fen = match self.history.first() {
+      Some(entry) => entry.fen_before.clone(),
+      None => self.board.to_fen(),
+    };
+
+    pgn::write(&tags, &start_fen, &self.history, result)
+  }
+
+  /// Save the game as PGN.
+  /// `filename`: &str
+  ///
+  /// Return: Result<(), ChessError>
+  pub fn save_pgn(&self, filename: &str) -> Result<(), ChessError> {
+    let mut file = File::create(filename)?;
+
+    file.write_all(self.to_pgn().as_bytes())?;
+
+    Ok(())
+  }
+
+  /// Write the PGN to the path set with set_pgn_path(), if any.
+  fn write_pgn(&self) {
+    if let Some(ref path) = self.pgn_path {
+      match self.save_pgn(path) {
+        Ok(_) => println!("Game saved to {}.\r", path),
+        Err(err) => println!("Could not save the game to {}: {}\r", path, err),
+      };
+    }
+  }
+
+  /// Replay a game read from PGN, from its FEN tag if it has one.
+  /// The moves can be stepped through with undo() and redo().
+  ///
+  /// Parameters:
+  /// `record`: &PgnGame
+  ///
+  /// Return: Result<(), ChessError> (the ply of the first illegal move; the game is unchanged then)
+  pub fn load_pgn_game(&mut self, record: &PgnGame) -> Result<(), ChessError> {
+    let mut game = Game::new(self.board.get_my_color());
+
+    if let Some(fen) = record.tag("FEN") {
+      game.load_fen(fen)?;
+    }
+
+    game.set_players(record.tag("White").unwrap_or("?"), record.tag("Black").unwrap_or("?"));
+
+    if let Some(date) = record.tag("Date") {
+      game.date = String::from(date);
+    }
+
+    for (i, notation) in record.moves.iter().enumerate() {
+      if let Err(err) = game.make_move(notation, true) {
+        return Err(ChessError::Replay{ply: i + 1, notation: notation.clone(), error: Box::new(err)});
+      }
+    }
+
+    game.pgn_path = self.pgn_path.take();
+    *self = game;
+
+    Ok(())
+  }
+
+  /// Load a game from a PGN file.
+  ///
+  /// Parameters:
+  /// `filename`: &str
+  /// `index`: usize, which game in the file, from 0
+  ///
+  /// Return: Result<(), ChessError>
+  pub fn load_pgn(&mut self, filename: &str, index: usize) -> Result<(), ChessError> {
+    let mut file = File::open(filename)?;
+    let mut contents = String::new();
+
+    file.read_to_string(&mut contents)?;
+
+    let games = pgn::read(&contents)?;
+
+    match games.get(index) {
+      Some(record) => self.load_pgn_game(record),
+      None => Err(ChessError::Parse(format!("{} has {} games, there is no game {}.", filename, games.len(), index + 1))),
+    }
+  }
+
+  /// Save a game
+  /// `filename`: &str
+  ///
+  /// Return: Result<(), ChessError>
+  pub fn save(&self, filename: &str) -> Result<(), ChessError> {
+    let board = self.serialize();
+
+    let mut file = File::create(filename)?;
+
+    file.write_all(board.as_bytes())?;
+
+    Ok(())
+  }
+
+  /// Load a game
+  /// `filename`: &str
+  ///
+  /// Return: Result<(), ChessError>
+  pub fn load(&mut self, filename: &str) -> Result<(), ChessError> {
+    let mut file = File::open(filename)?;
+
+    let mut contents = String::new();
+
+    file.read_to_string(&mut contents)?;
+
+    self.deserialize(&contents)
+  }
+
+  /// Replace the board with a position in Forsyth-Edwards Notation.
+  ///
+  /// Parameters:
+  /// `fen`: &str
+  ///
+  /// Return: Result<(), ChessError> (a parse error describing what is wrong with the FEN)
+  pub fn load_fen(&mut self, fen: &str) -> Result<(), ChessError> {
+    self.board = Board::from_fen(fen, self.board.get_my_color())?;
+    self.positions = vec![self.board.hash()];
+    self.history = Vec::new();
+    self.undone = Vec::new();
+
+    Ok(())
+  }
+
+  pub fn get_board(&self) -> Board {
+    return self.board.clone()
+  }
+
+  /// Start the game in a window.
+  ///
+  /// Parameters:
+  /// `settings`: &Settings
+  #[cfg(feature = "gui")]
+  pub fn start(settings: &Settings) {
+    // Board channel is for sending the board state to the GUI
+    // and the game thread
+    let (board_sender, board_receiver): (Sender<Board>, Receiver<Board>) = channel();
+
+    // Close channel is for telling the GUI to close if the game said so
+    let (close_sender, close_receiver): (Sender<bool>, Receiver<bool>) = channel();
+
+    // GUI channel is for the GUI to send us moves made through it
+    let (gui_sender, gui_receiver): (Sender<String>, Receiver<String>) = channel();
+
+    // OpenGL GUI, turned around once the game thread knows our color
+    let mut window = Window::new(756, 756, gui_sender, Color::White);
+
+    // All game logic runs in a separate thread; GUI runs in the main thread.
+    let mut game_settings = settings.clone();
+
+    // Set up the position first, in the same window
+    if settings.edit {
+      let fen = settings.fen.clone().unwrap_or(String::from(STARTING_FEN));
+
+      match window.edit(Board::from_fen(&fen, Color::White).unwrap_or(Board::new(Color::White))) {
+        Some(board) => game_settings.fen = Some(board.to_fen()),
+        None => return,
+      };
+    }
+
+    let handle = thread::spawn(move || {
+      Self::run(&game_settings, &Input::Gui(gui_receiver), &board_sender);
+
+      close_sender.send(true).unwrap();
+    });
+
+    while !window.should_close() {
+
+      // Get the new board
+      match board_receiver.recv_timeout(Duration::from_millis(MPSC_TIMEOUT_MS)) {
+        Ok(new_board) => {
+          // Update board with new or old board
+          window.update_board(new_board.clone());
+          println!("New board");
+        }
+        Err(_) => (),
+      };
+
+      // Close the GUI, maybe
+      match close_receiver.recv_timeout(Duration::from_millis(MPSC_TIMEOUT_MS)) {
+        Ok(close) => {
+          if close {
+            window.close();
+            println!("Close window");
+          }
+        },
+        Err(_) => (),
+      };
+
+      window.draw();
+    }
+
+    // Wait for the game thread to exist
+    handle.join().unwrap();
+  }
+
+  /// Start the game in the terminal, without a window. Moves are typed
+  /// (or piped) in and the board is printed after every move.
+  ///
+  /// Parameters:
+  /// `settings`: &Settings
+  pub fn start_headless(settings: &Settings) {
+    // Nobody draws the board, the sends go nowhere
+    let (board_sender, _): (Sender<Board>, Receiver<Board>) = channel();
+
+    let mut settings = settings.clone();
+
+    // Set up the position first
+    if settings.edit {
+      let fen = settings.fen.clone().unwrap_or(String::from(STARTING_FEN));
+
+      match editor::run(&fen, Color::White) {
+        Some(board) => settings.fen = Some(board.to_fen()),
+        None => return,
+      };
+    }
+
+    Self::run(&settings, &Input::Terminal, &board_sender);
+  }
+
+  /// Set up the game and play it until it's over or a player leaves.
+  ///
+  /// Parameters:
+  /// `settings`: &Settings
+  /// `input`: &Input, where our moves come from
+  /// `board_sender`: &Sender<Board>, the board after every change, e.g. for the GUI
+  fn run(settings: &Settings, input: &Input, board_sender: &Sender<Board>) {
+    let fen = settings.fen.clone().unwrap_or(String::from(STARTING_FEN));
+
+    let client = match settings.opponent {
+      Opponent::Local => Client::new("self"),
+      Opponent::Engine => Client::engine(&fen),
+      Opponent::Host(ref addr) => Client::host(addr),
+      Opponent::Join(ref addr) => Client::new(addr),
+    };
+
+    let mut client = match client {
+      Ok(client) => client,
+      Err(err) => {
+        println!("{}", err);
+        return;
+      },
+    };
+
+    let me = env::var("USER").unwrap_or(String::from("?"));
+
+    // Players over TCP agree on colors; the HTTP server pairs players without asking
+    let negotiate = match settings.opponent {
+      Opponent::Host(ref addr) | Opponent::Join(ref addr) => addr.starts_with("tcp://"),
+      _ => false,
+    };
+
+    let my_color = match negotiate {
+      true => match client.negotiate_color(&me, settings.color.as_ref()) {
+        Ok(color) => color,
+        Err(err) => {
+          println!("{}", err);
+          return;
+        },
+      },
+
+      false => match settings.color {
+        Some(ref choice) => choice.resolve(),
+
+        // Whoever joins moves second
+        None => match settings.opponent {
+          Opponent::Join(_) => Color::Black,
+          _ => Color::White,
+        },
+      },
+    };
+
+    let mut game = Self::new(my_color.clone());
+
+    if let Err(err) = game.load_fen(&fen) {
+      println!("{}", err);
+      return;
+    }
+
+    println!("\r\nYou play {}.\r", Self::color_name(&my_color));
+
+    // Name the players for the PGN
+    let other = match client.get_name() {
+      "" => String::from("?"),
+      name => String::from(name),
+    };
+
+    match my_color {
+      Color::Black => game.set_players(&other, &me),
+      _ => game.set_players(&me, &other),
+    };
+
+    game.set_pgn_path(PGN_FILE);
+
+    if let Some(ref control) = settings.time_control {
+      game.set_time_control(control.clone());
+    }
+
+    println!("\r\nWelcome to Rust Chess!\r\nType 'exit' to quit the game.");
+
+    // White's clock starts with the game
+    game.start_clock();
+
+    // Game loop
+    loop { 
+
+      // Print the board to the terminal (helps debugging and whatnot)
+      println!("\n\r{}\n\r", game);
+
+      // Initial render of the chess board
+      let _ = board_sender.send(game.get_board());
+
+      // The board knows whose turn it is
+      let should_exit = match game.board.get_turn() == my_color {
+        // Loop until a valid move is made or we exit
+        true => game.my_turn(&mut client, input, board_sender),
+        false => game.other_player_turn(&mut client, board_sender),
+      };
+
+      if should_exit || game.is_over() {
+        game.stop_clock();
+        game.write_pgn();
+
+        if let Some(ref path) = settings.save_path {
+          match game.save(path) {
+            Ok(_) => println!("Game saved to {}.\r", path),
+            Err(err) => println!("Could not save the game to {}: {}\r", path, err),
+          };
+        }
+
+        break;
+      }
+    }
+  }
+
+  pub fn my_turn(&mut self, client: &mut Client, input_source: &Input, board_sender: &Sender<Board>) -> bool {
+    // Loop until a valid move is made or we exit
+    let mut should_exit = false;
+
+    let mut input = String::new();
+
+    loop {
+      let mut received_input = false;
+
+      while !received_input {
+        // Our flag fell while thinking
+        if self.is_flagged() {
+          return false;
+        }
+
+        match input_source.next() {
+          Some(line) => {
+            received_input = true;
+            input = line;
+          },
+          None => (), // Received nothing yet
+        };
+      }
+
+      if input.as_str() == "exit" {
+
+        client.send_message(Message::Bye, "");
+
+        should_exit = true;
+        break;
+      }
+
+      // Take back our last move and the reply to it, only if the other player is on this computer
+      else if input.as_str() == "undo" {
+        if !client.local {
+          println!("Moves can't be taken back in a network game.");
+        }
+
+        else if self.take_back() {
+          client.send_message(Message::Undo, "");
+          println!("\n\r{}\n\r", self);
+        }
+
+        let _ = board_sender.send(self.board.clone());
+      }
+
+      else {
+        // Make move
+        match self.make_move(&input, false) {
+          Ok(_) => {
+            self.press_clock();
+
+            // Our clock first, so it's there when the move arrives
+            if let Some(times) = self.clock_payload() {
+              client.send_message(Message::Clock, &times);
+            }
+
+            // Tell the other player about it
+            // in coordinates, the protocol doesn't need to know the board
+            let played = self.history.last().unwrap().chess_move.to_string();
+            client.send_message(Message::MakeMove, &played);
+
+            // Print board
+            println!("\n\r{}\n\r", self);
+            self.announce_last_move();
+            break;
+          },
+
+          // Out of time, or the game ended some other way
+          Err(ChessError::GameOver(_)) => break,
+
+          Err(err) => {
+            println!("{}", err);
+            println!("{}", input);
+            
+          }
+        };
+
+        let _ = board_sender.send(self.board.clone());
+        should_exit = false;
+      }
+    }
+
+    should_exit
+  }
+
+  pub fn other_player_turn(&mut self, client: &mut Client, board_sender: &Sender<Board>) -> bool {
+    let mut should_exit = false;
+
+    // The other player's clock, sent before their move
+    let mut clock_times: Option<String> = None;
+
+    // Loop until a valid move is received
+    loop {
+      // Wait for other player to make move
+      let (msg_type, msg_payload) = match client.get_message() {
+        Ok(Some((msg_type, msg_payload))) => (msg_type, msg_payload),
+
+        // Nothing yet, their flag may fall while we wait
+        Ok(None) => {
+          if self.is_flagged() {
+            return false;
+          }
+
+          thread::sleep(Duration::from_millis(MPSC_TIMEOUT_MS));
+          continue;
+        },
+
+        // Tell other player we didn't understand
+        Err(ChessError::Parse(err)) => {
+          println!("{}", err);
+          client.send_message(Message::BadMessage, "");
+          continue;
+        },
+
+        // The connection is gone, nobody is going to move
+        Err(err) => {
+          println!("{}", err);
+          should_exit = true;
+          break;
+        },
+      };
+
+      match msg_type {
+
+        // Other player is exiting game
+        Message::Bye => { should_exit = true; break; },
+
+        // Other player is making a move
+        Message::MakeMove => {
+          // Make the move on our board
+          match self.make_move(&msg_payload, true) {
+            Ok(_) => { 
+              self.press_clock();
+
+              if let Some(ref times) = clock_times {
+                let mover = self.board.get_turn().opposite();
+                self.sync_clock(&mover, times);
+              }
+
+              // Print board
+              println!("\n\r{}\n\r", self);
+              self.announce_last_move();
+              let _ = board_sender.send(self.board.clone());
+              break; 
+            },
+
+            // Their flag fell before the move arrived
+            Err(ChessError::GameOver(_)) => break,
+            
+            Err(err) => {
+              println!("{}", err);
+              println!("{}", &msg_payload);
+
+              // Tell other player bad move was made
+              client.send_message(Message::BadMessage, "");
+              continue;
+            }
+          }
+        },
+
+        // Other player's clock after their move, the move comes next
+        Message::Clock => {
+          clock_times = Some(msg_payload);
+          continue;
+        },
+
+        // Other player is taking back their last move, it's still their turn;
+        // nobody takes back moves over the network
+        Message::Undo => {
+          if !client.local {
+            client.send_message(Message::BadMessage, "");
+          }
+
+          else if self.take_back() {
+            println!("\n\r{}\n\r", self);
+            let _ = board_sender.send(self.board.clone());
+          }
+
+          continue;
+        },
+
+        // Unhandled; TODO: handle.
+        _ => { continue; },
+      }
+    }
+
+    should_exit
+  }
+}
+
+// Display
+impl fmt::Display for Game {
 
   // fmt
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     write!(f, "{}", self.board)?;
 
+    if let Some(ref clock) = self.clock {
+      write!(f, "\n\r{}", clock)?;
+    }
+
     Ok(())
   }
 
@@ -384,6 +1244,7 @@ impl fmt::Display for Game {
 mod test {
   // Game
   use super::*;
+  use board::PieceKind;
 
   #[test]
   fn test_save_load() {
@@ -397,4 +1258,565 @@ mod test {
 
     let _ = game2.load("test.json");
   }
+
+  #[test]
+  fn test_undo_redo() {
+    let mut game = Game::new(Color::White);
+
+    assert!(game.undo().is_none());
+
+    for notation in ["e2e4", "d7d5", "e4d5", "d8d5"].iter() {
+      assert!(game.make_move(notation, true).is_ok());
+    }
+
+    assert_eq!(game.history().len(), 4);
+    assert_eq!(game.history()[2].san, "exd5");
+    assert_eq!(game.history()[2].chess_move.kind, MoveKind::Capture);
+    assert_eq!(game.history()[2].captured, Some(Piece{kind: PieceKind::Pawn, color: Color::Black}));
+
+    let queen_takes = game.undo().unwrap();
+    assert_eq!(queen_takes.chess_move.to_string(), "d8d5");
+    assert_eq!(game.get_board().get_turn(), Color::Black);
+
+    let pawn_takes = game.undo().unwrap();
+    assert_eq!(pawn_takes.chess_move.to_string(), "e4d5");
+    assert_eq!(game.get_board().to_fen(), "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2");
+
+    // The move can't be played on another board, but it isn't lost
+    let board = game.board.clone();
+    game.board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1", Color::White).unwrap();
+
+    assert!(game.redo().is_none());
+    assert_eq!(game.history().len(), 2);
+
+    game.board = board;
+
+    assert_eq!(game.redo(), Some(pawn_takes));
+    assert_eq!(game.history().len(), 3);
+
+    // A different move, nothing left to redo
+    assert!(game.make_move("c8g4", true).is_ok());
+    assert!(game.redo().is_none());
+    assert_eq!(game.history().len(), 4);
+  }
+
+  #[test]
+  fn test_undo_repetition() {
+    let mut game = Game::new(Color::White);
+    let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+    for notation in shuffle.iter().chain(shuffle.iter()) {
+      assert!(game.make_move(notation, true).is_ok());
+    }
+
+    assert_eq!(game.status(), GameStatus::ThreefoldRepetition);
+
+    assert!(game.take_back());
+    assert_eq!(game.status(), GameStatus::InProgress);
+    assert_eq!(game.repetitions(), 2);
+  }
+
+  #[test]
+  fn test_save_load_history() {
+    let mut game = Game::new(Color::White);
+
+    for notation in ["e2e4", "e7e5", "g1f3"].iter() {
+      assert!(game.make_move(notation, true).is_ok());
+    }
+
+    assert!(game.save("test_history.json").is_ok());
+
+    let mut loaded = Game::new(Color::White);
+
+    assert!(loaded.load("test_history.json").is_ok());
+    assert_eq!(loaded.history(), game.history());
+    assert_eq!(loaded.get_board().to_fen(), game.get_board().to_fen());
+
+    // Moves can be taken back after loading
+    assert_eq!(loaded.undo().unwrap().chess_move.to_string(), "g1f3");
+
+    // Old saves only have the board
+    assert!(loaded.deserialize(&game.get_board().serialize()).is_ok());
+    assert!(loaded.history().is_empty());
+    assert_eq!(loaded.get_board().to_fen(), game.get_board().to_fen());
+
+    let _ = std::fs::remove_file("test_history.json");
+  }
+
+  #[test]
+  fn test_san_moves() {
+    let mut game = Game::new(Color::White);
+
+    for notation in ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Bxc6", "dxc6", "O-O"].iter() {
+      assert!(game.make_move(notation, true).is_ok(), "{}", notation);
+    }
+
+    let played: Vec<String> = game.history().iter().map(|entry| entry.chess_move.to_string()).collect();
+    assert_eq!(played, vec!["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "b5c6", "d7c6", "e1g1"]);
+
+    // Coordinates still work, and the history has both
+    assert!(game.make_move("d8d4", true).is_ok());
+    assert_eq!(game.history().last().unwrap().san, "Qd4");
+
+    match game.make_move("Nxe5", true) {
+      Ok(_) => assert_eq!(game.history().last().unwrap().chess_move.to_string(), "f3e5"),
+      Err(err) => panic!("{}", err),
+    };
+  }
+
+  #[test]
+  fn test_pgn() {
+    let mut game = Game::new(Color::White);
+    game.set_players("Lev", "Rust \"Bot\"");
+
+    for notation in ["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6"].iter() {
+      assert!(game.make_move(notation, true).is_ok());
+    }
+
+    let pgn = game.to_pgn();
+
+    assert!(pgn.starts_with("[Event \"Casual game\"]\n[Site \"?\"]\n[Date \""));
+    assert!(pgn.contains("[Round \"-\"]\n[White \"Lev\"]\n[Black \"Rust \\\"Bot\\\"\"]\n[Result \"*\"]\n\n"));
+    assert!(pgn.ends_with("\n1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 *\n\n"));
+    assert!(!pgn.contains("[FEN"));
+
+    assert!(game.make_move("Qxf7", true).is_ok());
+
+    let pgn = game.to_pgn();
+
+    assert!(pgn.contains("[Result \"1-0\"]"));
+    assert!(pgn.ends_with("3. Qh5 Nf6 4. Qxf7# 1-0\n\n"));
+  }
+
+  #[test]
+  fn test_pgn_from_position() {
+    let mut game = Game::new(Color::White);
+
+    assert!(game.load_fen("4k3/8/8/8/8/8/8/R3K3 b - - 10 40").is_ok());
+    assert!(game.make_move("Kd7", true).is_ok());
+    assert!(game.make_move("Ra7+", true).is_ok());
+
+    let pgn = game.to_pgn();
+
+    assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/8/R3K3 b - - 10 40\"]\n"));
+    assert!(pgn.ends_with("\n40... Kd7 41. Ra7+ *\n\n"));
+
+  }
+
+  #[test]
+  fn test_pgn_opera_game() {
+    let mut game = Game::new(Color::White);
+    let moves = "e4 e5 Nf3 d6 d4 Bg4 dxe5 Bxf3 Qxf3 dxe5 Bc4 Nf6 Qb3 Qe7 Nc3 c6 Bg5 b5 Nxb5 cxb5 \
+      Bxb5+ Nbd7 O-O-O Rd8 Rxd7 Rxd7 Rd1 Qe6 Bxd7+ Nxd7 Qb8+ Nxb8 Rd8#";
+
+    for notation in moves.split_whitespace() {
+      assert!(game.make_move(notation, true).is_ok(), "{}", notation);
+    }
+
+    assert_eq!(game.status(), GameStatus::Checkmate);
+
+    // Long games are wrapped
+    let pgn = game.to_pgn();
+    let movetext: Vec<&str> = pgn.lines().skip_while(|line| !line.is_empty()).skip(1).take_while(|line| !line.is_empty()).collect();
+
+    assert_eq!(movetext, vec![
+      "1. e4 e5 2. Nf3 d6 3. d4 Bg4 4. dxe5 Bxf3 5. Qxf3 dxe5 6. Bc4 Nf6 7. Qb3 Qe7 8.",
+      "Nc3 c6 9. Bg5 b5 10. Nxb5 cxb5 11. Bxb5+ Nbd7 12. O-O-O Rd8 13. Rxd7 Rxd7 14.",
+      "Rd1 Qe6 15. Bxd7+ Nxd7 16. Qb8+ Nxb8 17. Rd8# 1-0",
+    ]);
+  }
+
+  #[test]
+  fn test_load_pgn() {
+    let mut game = Game::new(Color::White);
+
+    assert!(game.load_pgn("tests/fixtures/games.pgn", 0).is_ok());
+    assert_eq!(game.history().len(), 33);
+    assert_eq!(game.status(), GameStatus::Checkmate);
+    assert!(game.to_pgn().contains("[White \"Paul Morphy\"]"));
+    assert!(game.to_pgn().contains("[Date \"1858.??.??\"]"));
+
+    // Step back through it
+    assert_eq!(game.undo().unwrap().san, "Rd8#");
+    assert_eq!(game.undo().unwrap().san, "Nxb8");
+    assert_eq!(game.status(), GameStatus::Check);
+
+    // Continue a game from a position
+    assert!(game.load_pgn("tests/fixtures/games.pgn", 1).is_ok());
+    assert!(game.make_move("Kc8", true).is_ok());
+
+    // The third game goes wrong on black's second move
+    match game.load_pgn("tests/fixtures/games.pgn", 2) {
+      Err(ChessError::Replay{ply, notation, error}) => {
+        assert_eq!(ply, 4);
+        assert_eq!(notation, "Ke6");
+        assert!(error.to_string().contains("Ke6"));
+      },
+      other => panic!("Expected an illegal move: {:?}", other),
+    };
+
+    // Nothing changed
+    assert_eq!(game.history().last().unwrap().san, "Kc8");
+
+    match game.load_pgn("tests/fixtures/games.pgn", 3) {
+      Err(ChessError::Parse(_)) => (),
+      other => panic!("Expected a missing game: {:?}", other),
+    };
+  }
+
+  #[test]
+  fn test_save_format() {
+    let mut game = Game::new(Color::Black);
+    game.set_players("Lev", "?");
+
+    for notation in ["e4", "c5", "Nf3"].iter() {
+      assert!(game.make_move(notation, true).is_ok());
+    }
+
+    let json = game.serialize();
+    let saved: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(saved["version"], json!(save::SAVE_VERSION));
+    assert_eq!(saved["players"]["white"], json!("Lev"));
+    assert_eq!(saved["my_color"], json!("Black"));
+    assert_eq!(saved["start_fen"], json!(::board::STARTING_FEN));
+    assert_eq!(saved["moves"][1], json!({"move": "c7c5", "san": "c5"}));
+
+    let mut loaded = Game::new(Color::White);
+
+    assert!(loaded.deserialize(&json).is_ok());
+    assert_eq!(loaded.history(), game.history());
+    assert_eq!(loaded.get_board().get_my_color(), Color::Black);
+    assert_eq!(loaded.to_pgn(), game.to_pgn());
+
+    // Version 1 files, with the board and history but no version
+    let version_one = json!({
+      "board": serde_json::from_str::<serde_json::Value>(&game.get_board().serialize()).unwrap(),
+      "history": game.history(),
+    });
+
+    let mut migrated = Game::new(Color::White);
+
+    assert!(migrated.deserialize(&version_one.to_string()).is_ok());
+    assert_eq!(migrated.history(), game.history());
+  }
+
+  #[test]
+  fn test_load_errors() {
+    let mut game = Game::new(Color::White);
+    assert!(game.make_move("e4", true).is_ok());
+
+    let json = game.serialize();
+
+    // An illegal move
+    let mut saved: serde_json::Value = serde_json::from_str(&json).unwrap();
+    saved["moves"][0]["move"] = json!("e2e5");
+
+    match game.deserialize(&saved.to_string()) {
+      Err(ChessError::Replay{ply: 1, ..}) => (),
+      other => panic!("Expected an illegal move: {:?}", other),
+    };
+
+    // Moves and position don't agree
+    let mut saved: serde_json::Value = serde_json::from_str(&json).unwrap();
+    saved["fen"] = json!(::board::STARTING_FEN);
+
+    match game.deserialize(&saved.to_string()) {
+      Err(ChessError::Parse(_)) => (),
+      other => panic!("Expected a parse error: {:?}", other),
+    };
+
+    match game.deserialize("{\"board\": ") {
+      Err(ChessError::Parse(_)) => (),
+      other => panic!("Expected a parse error: {:?}", other),
+    };
+
+    // The game is still there
+    assert_eq!(game.history().len(), 1);
+  }
+
+  #[test]
+  fn test_time_forfeit() {
+    let mut game = Game::new(Color::White);
+    game.set_time_control("40/90+30,30+30".parse().unwrap());
+    game.start_clock();
+
+    assert!(game.make_move("e4", true).is_ok());
+    game.press_clock();
+
+    assert_eq!(game.status(), GameStatus::InProgress);
+    assert!(game.clock().unwrap().remaining(&Color::White) > Duration::from_secs(5400));
+
+    // Black thinks for too long
+    game.clock.as_mut().unwrap().press_after(&Color::Black, Duration::from_secs(5400));
+
+    assert_eq!(game.status(), GameStatus::TimeForfeit);
+    assert!(!game.status().is_draw());
+    assert!(game.to_pgn().contains("[Result \"1-0\"]"));
+
+    match game.make_move("e5", true) {
+      Err(ChessError::GameOver(GameStatus::TimeForfeit)) => (),
+      other => panic!("Expected the game to be over: {:?}", other),
+    };
+  }
+
+  #[test]
+  fn test_save_load_clock() {
+    let mut game = Game::new(Color::White);
+    game.set_time_control("3d5".parse().unwrap());
+
+    assert!(game.make_move("d4", true).is_ok());
+    game.clock.as_mut().unwrap().press_after(&Color::White, Duration::from_secs(25));
+
+    let mut loaded = Game::new(Color::White);
+    assert!(loaded.deserialize(&game.serialize()).is_ok());
+
+    let clock = loaded.clock().unwrap();
+
+    assert_eq!(clock.time_control().to_string(), "3d5");
+    assert_eq!(clock.remaining(&Color::White), Duration::from_secs(160));
+    assert_eq!(clock.moves(&Color::White), 1);
+    assert_eq!(clock.flagged(), None);
+
+    // The other player's clock, as they sent it
+    loaded.sync_clock(&Color::White, "150000 170500");
+    assert_eq!(loaded.clock().unwrap().remaining(&Color::White), Duration::from_secs(150));
+    assert_eq!(loaded.clock().unwrap().remaining(&Color::Black), Duration::from_secs(180));
+
+    // Untimed games don't have a clock
+    let mut untimed = Game::new(Color::White);
+    assert!(untimed.deserialize(&Game::new(Color::White).serialize()).is_ok());
+    assert!(untimed.clock().is_none());
+  }
+
+  #[test]
+  fn test_undo_clock() {
+    let mut game = Game::new(Color::White);
+    game.set_time_control("5+3".parse().unwrap());
+
+    assert!(game.make_move("e4", true).is_ok());
+    game.clock.as_mut().unwrap().press_after(&Color::White, Duration::from_secs(20));
+
+    assert!(game.make_move("e5", true).is_ok());
+    game.clock.as_mut().unwrap().press_after(&Color::Black, Duration::from_secs(40));
+
+    assert_eq!(game.clock().unwrap().remaining(&Color::Black), Duration::from_secs(263));
+
+    // Both players get their time back, and the moves don't count anymore;
+    // white's clock is running again
+    assert!(game.take_back());
+
+    let clock = game.clock().unwrap();
+
+    assert!(clock.is_running());
+    assert!(Duration::from_secs(300) - clock.remaining(&Color::White) < Duration::from_secs(1));
+    assert_eq!(clock.remaining(&Color::Black), Duration::from_secs(300));
+    assert_eq!(clock.moves(&Color::White), 0);
+    assert_eq!(clock.moves(&Color::Black), 0);
+  }
+
+  #[test]
+  fn test_input() {
+    let (sender, receiver) = channel();
+    let input = Input::Gui(receiver);
+
+    // Nothing yet, the game keeps waiting
+    assert_eq!(input.next(), None);
+
+    sender.send(String::from("e2e4")).unwrap();
+    assert_eq!(input.next(), Some(String::from("e2e4")));
+  }
+
+  #[test]
+  fn test_errors() {
+    let mut game = Game::new(Color::White);
+
+    match game.make_move("e2e9", false) {
+      Err(ChessError::BadNotation(_)) => (),
+      other => panic!("Expected bad notation: {:?}", other),
+    };
+
+    match game.make_move("e3e4", false) {
+      Err(ChessError::EmptySquare(square)) => assert_eq!(square.to_string(), "e3"),
+      other => panic!("Expected an empty square: {:?}", other),
+    };
+
+    match game.make_move("e7e5", false) {
+      Err(ChessError::WrongOwner(_)) => (),
+      other => panic!("Expected the wrong owner: {:?}", other),
+    };
+
+    match game.make_move("e7e5", true) {
+      Err(ChessError::NotYourTurn) => (),
+      other => panic!("Expected the wrong turn: {:?}", other),
+    };
+
+    match game.make_move("e2e5", false) {
+      Err(ChessError::IllegalMove(m)) => assert_eq!(m.to_string(), "e2e5"),
+      other => panic!("Expected an illegal move: {:?}", other),
+    };
+
+    assert!(game.load_fen("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1").is_ok());
+
+    match game.make_move("e1d2", false) {
+      Err(ChessError::KingInCheck(_)) => (),
+      other => panic!("Expected the king in check: {:?}", other),
+    };
+
+    match game.load_fen("not a fen") {
+      Err(ChessError::Parse(_)) => (),
+      other => panic!("Expected a parse error: {:?}", other),
+    };
+
+    match game.load("does_not_exist.json") {
+      Err(ChessError::Io(_)) => (),
+      other => panic!("Expected an I/O error: {:?}", other),
+    };
+  }
+
+  #[test]
+  fn test_scholars_mate() {
+    let mut game = Game::new(Color::White);
+
+    for notation in &["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6"] {
+      assert!(game.make_move(notation, true).is_ok());
+    }
+
+    assert_eq!(game.status(), GameStatus::InProgress);
+    assert!(game.make_move("h5f7", true).is_ok());
+
+    assert_eq!(game.status(), GameStatus::Checkmate);
+    assert!(game.status().is_over());
+  }
+
+  #[test]
+  fn test_stalemate() {
+    let mut game = Game::new(Color::White);
+
+    // Sam Loyd's ten move stalemate
+    let moves = [
+      "e2e3", "a7a5", "d1h5", "a8a6", "h5a5", "h7h5", "h2h4", "a6h6", "a5c7", "f7f6",
+      "c7d7", "e8f7", "d7b7", "d8d3", "b7b8", "d3h7", "b8c8", "f7g6", "c8e6",
+    ];
+
+    for notation in moves.iter() {
+      assert!(game.make_move(notation, true).is_ok(), "{}", notation);
+    }
+
+    assert_eq!(game.status(), GameStatus::Stalemate);
+  }
+
+  #[test]
+  fn test_castling_notation() {
+    let mut game = Game::new(Color::White);
+
+    for notation in &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6"] {
+      assert!(game.make_move(notation, true).is_ok());
+    }
+
+    // Ours
+    assert!(game.make_move("O-O", false).is_ok());
+
+    // Theirs, blocked by the queen and the bishop
+    assert!(game.make_move("O-O-O", true).is_err());
+    assert!(game.make_move("f8c5", true).is_ok());
+    assert!(game.make_move("d2d3", false).is_ok());
+    assert!(game.make_move("0-0", true).is_ok());
+
+    assert!(game.make_move("O-O-O-O", false).is_err());
+  }
+
+  #[test]
+  fn test_load_fen() {
+    let mut game = Game::new(Color::White);
+
+    assert!(game.load_fen("not a fen").is_err());
+
+    // Back rank mate in one
+    assert!(game.load_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").is_ok());
+    assert!(game.make_move("a1a8", false).is_ok());
+    assert_eq!(game.status(), GameStatus::Checkmate);
+    assert_eq!(game.get_board().to_fen(), "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1");
+  }
+
+  #[test]
+  fn test_repetition() {
+    let mut game = Game::new(Color::White);
+    let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+    for notation in shuffle.iter().chain(shuffle.iter()) {
+      assert!(!game.status().is_over());
+      assert!(game.make_move(notation, true).is_ok());
+    }
+
+    assert_eq!(game.repetitions(), 3);
+    assert_eq!(game.status(), GameStatus::ThreefoldRepetition);
+
+    // The draw is claimed, no more moves
+    match game.make_move("g1f3", true) {
+      Err(ChessError::GameOver(GameStatus::ThreefoldRepetition)) => (),
+      other => panic!("Expected the game to be over: {:?}", other),
+    };
+
+    // Two more repetitions, as if nobody had claimed the draw
+    let position = game.board.hash();
+    game.positions.push(position);
+    game.positions.push(position);
+
+    assert_eq!(game.status(), GameStatus::FivefoldRepetition);
+    assert!(game.status().is_draw());
+  }
+
+  #[test]
+  fn test_move_rules() {
+    let mut game = Game::new(Color::White);
+
+    assert!(game.load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").is_ok());
+    assert_eq!(game.status(), GameStatus::InProgress);
+    assert!(game.make_move("a1a2", false).is_ok());
+    assert_eq!(game.status(), GameStatus::FiftyMoveRule);
+
+    // The draw is claimed, no more moves
+    assert!(game.make_move("e8e7", false).is_err());
+
+    assert!(game.load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 150 80").is_ok());
+    assert_eq!(game.status(), GameStatus::SeventyFiveMoveRule);
+
+    // A pawn move starts the count again
+    assert!(game.load_fen("4k3/8/8/8/8/8/P7/R3K3 w - - 99 80").is_ok());
+    assert!(game.make_move("a2a3", false).is_ok());
+    assert_eq!(game.status(), GameStatus::InProgress);
+
+    // Mate on the last move still counts
+    assert!(game.load_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 80").is_ok());
+    assert!(game.make_move("a1a8", false).is_ok());
+    assert_eq!(game.status(), GameStatus::Checkmate);
+  }
+
+  #[test]
+  fn test_insufficient_material() {
+    let mut game = Game::new(Color::White);
+
+    assert!(game.load_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").is_ok());
+    assert_eq!(game.status(), GameStatus::InsufficientMaterial);
+
+    // Capturing the last piece
+    assert!(game.load_fen("4k3/8/8/8/8/8/3r4/4K3 w - - 0 1").is_ok());
+    assert_eq!(game.status(), GameStatus::InProgress);
+    assert!(game.make_move("e1d2", false).is_ok());
+    assert_eq!(game.status(), GameStatus::InsufficientMaterial);
+  }
+
+  #[test]
+  fn test_promotion_notation() {
+    let mut game = Game::new(Color::White);
+
+    for notation in &["a2a4", "b7b5", "a4b5", "a7a6", "b5a6", "c8b7", "a6b7", "b8c6"] {
+      assert!(game.make_move(notation, true).is_ok(), "{}", notation);
+    }
+
+    assert!(game.make_move("b7a8", false).is_err());
+    assert!(game.make_move("b7a8x", false).is_err());
+    assert!(game.make_move("b7a8q", false).is_ok());
+  }
 }
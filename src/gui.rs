@@ -8,7 +8,7 @@ extern crate glfw;
 use glfw::{Key, Action, Context, MouseButton};
 
 // Math
-use cgmath::{Matrix, Matrix4, One};
+use cgmath::{Matrix, Matrix4, One, Vector3};
 
 // OpenGL camera
 use camera;
@@ -20,7 +20,8 @@ use models::piece::Piece as PieceModel;
 
 // chess board
 use board::Board;
-use board::Color;
+use board::{Color, Piece, PieceKind, Square};
+use editor;
 
 // Std
 use std::ffi::CString;
@@ -66,6 +67,17 @@ const fragment_shader_source: &str = r#"
     }
 "#;
 
+const window_title: &str = "Rust Chess";
+const promotion_title: &str = "Rust Chess - Promote to: (Q)ueen, (R)ook, (B)ishop or k(N)ight";
+const editor_title: &str = "Rust Chess - Drag pieces from the right, Tab changes the side to move, Enter to play";
+
+/// The pieces next to the board in the editor, top to bottom.
+/// White ones are in the first column, black ones in the second.
+const palette: [PieceKind; 6] = [
+  PieceKind::King, PieceKind::Queen, PieceKind::Rook,
+  PieceKind::Bishop, PieceKind::Knight, PieceKind::Pawn,
+];
+
 #[allow(dead_code)]
 pub struct Window {
   // Window width
@@ -103,6 +115,19 @@ pub struct Window {
 
   // The state of drag & drop.
   dragging: bool,
+
+  // Pawn move waiting for the player to pick what the pawn becomes.
+  pending_promotion: Option<String>,
+
+  // Backspace is held down, only take back one move per press.
+  undo_pressed: bool,
+
+  // Setting up a position: the board shares the window with the palette.
+  editing: bool,
+
+  // Tab and Enter are held down in the editor.
+  turn_pressed: bool,
+  done_pressed: bool,
 }
 
 impl Window {
@@ -138,6 +163,11 @@ impl Window {
       should_close: false,
       gui_sender,
       dragging: false,
+      pending_promotion: None,
+      undo_pressed: false,
+      editing: false,
+      turn_pressed: false,
+      done_pressed: false,
     };
 
     window.draw();
@@ -165,6 +195,52 @@ impl Window {
     self.buffer();
   }
 
+  /// Set up a position by dragging pieces from the palette next to the board,
+  /// or off the board to take them away. Tab changes the side to move and
+  /// Enter checks the position; the kings and rooks that can castle will.
+  ///
+  /// Parameters:
+  /// `board`: Board, the position to start from
+  ///
+  /// Return: Option<Board> (the position to play, None if the window was closed)
+  pub fn edit(&mut self, board: Board) -> Option<Board> {
+    self.board = board;
+    self.editing = true;
+    self.layout();
+    self.window.set_title(editor_title);
+
+    while self.editing && !self.should_close() {
+      self.draw();
+    }
+
+    self.editing = false;
+    self.layout();
+    self.window.set_title(window_title);
+
+    match self.should_close() {
+      true => None,
+      false => Some(self.board.clone()),
+    }
+  }
+
+  /// Make room for the palette while editing, the board stays square.
+  fn layout(&mut self) {
+    self.width = match self.editing {
+      true => self.height * 10 / 8,
+      false => self.height,
+    };
+
+    self.window.set_size(self.width as i32, self.height as i32);
+
+    let (width, height) = self.window.get_framebuffer_size();
+
+    unsafe {
+      gl::Viewport(0, 0, width, height);
+    }
+
+    self.buffer();
+  }
+
   /// Start OpenGL and GLFW
   fn init_glfw(width: u32, height: u32) -> (
     Box<glfw::Glfw>,
@@ -188,7 +264,7 @@ impl Window {
     glfw.window_hint(glfw::WindowHint::Samples(Some(4)));
 
     // Create window
-    let (mut window, events) = glfw.create_window(width, height, "Rust Chess", glfw::WindowMode::Windowed)
+    let (mut window, events) = glfw.create_window(width, height, window_title, glfw::WindowMode::Windowed)
     .expect("Failed to create GLFW window.");
 
     // Make current context
@@ -299,6 +375,21 @@ impl Window {
     }
   }
 
+  /// Black sees the board turned around, so a square on the board
+  /// is somewhere else on the screen. Works both ways.
+  ///
+  /// Parameters:
+  /// `my_color`: &Color, the side at the bottom of the screen
+  /// `position`: (usize, usize), x, y on the board (or the screen)
+  ///
+  /// Return: (usize, usize), x, y on the screen (or the board); off the board stays off
+  fn orient(my_color: &Color, position: (usize, usize)) -> (usize, usize) {
+    match *my_color == Color::Black && position.0 < 8 && position.1 < 8 {
+      true => (7 - position.0, 7 - position.1),
+      false => position,
+    }
+  }
+
   /// Draw the chess board
   fn buffer(&mut self) {
     // Let's get rid of everything for now
@@ -309,7 +400,9 @@ impl Window {
 
     self.models.push(Box::new(board));
 
-    // Draw the pieces
+    // Draw the pieces, from our side of the board
+    let my_color = self.board.get_my_color();
+
     for y in 0..8 {
       for x in 0..8 {
         // No piece, no drawing
@@ -317,13 +410,98 @@ impl Window {
           continue;
         }
 
+        let (screen_x, screen_y) = Self::orient(&my_color, (x, y));
+
         let mut piece = PieceModel::new(
-          self.program, x, y, self.board.get_color((x, y))
+          self.program, screen_x, screen_y, self.board.get_color((x, y))
         );
 
         self.models.push(Box::new(piece));
       }
     }
+
+    // Pieces to drag onto the board, right of it
+    if self.editing {
+      // The pieces all look alike for now, the row says what they are
+      for y in 0..palette.len() {
+        for (x, color) in [Color::White, Color::Black].iter().enumerate() {
+          let piece = PieceModel::new(self.program, 8 + x, y, color.clone());
+
+          self.models.push(Box::new(piece));
+        }
+      }
+    }
+  }
+
+  /// The palette piece at a spot on the screen.
+  ///
+  /// Parameters:
+  /// `position`: (usize, usize), x, y on the screen, the palette starts at x = 8
+  ///
+  /// Return: Option<Piece> (None if there is no palette piece there)
+  fn palette_piece(position: (usize, usize)) -> Option<Piece> {
+    let color = match position.0 {
+      8 => Color::White,
+      9 => Color::Black,
+      _ => return None,
+    };
+
+    palette.get(position.1).map(|kind| Piece{kind: kind.clone(), color})
+  }
+
+  /// A piece was dragged in the editor: from the palette onto the board,
+  /// from one square to another, or off the board.
+  ///
+  /// Parameters:
+  /// `from`: (usize, usize), x, y on the board, or in the palette
+  /// `to`: (usize, usize), x, y on the board, anywhere else takes the piece away
+  fn drop_edit(&mut self, from: (usize, usize), to: (usize, usize)) {
+    let piece = match Self::palette_piece(from) {
+      Some(piece) => Some(piece),
+      None => Square::from_position(from).and_then(|square| self.board.remove(square)),
+    };
+
+    if let (Some(piece), Some(square)) = (piece, Square::from_position(to)) {
+      self.board.place(square, piece);
+    }
+
+    println!("{}", self.board.to_fen());
+
+    self.buffer();
+  }
+
+  /// Tab changes the side to move, Enter finishes if the position can be played.
+  fn process_editor_keys(&mut self) {
+    match self.window.get_key(Key::Tab) {
+      Action::Press if !self.turn_pressed => {
+        self.turn_pressed = true;
+
+        let turn = self.board.get_turn().opposite();
+        self.board.set_turn(turn);
+
+        println!("{}", self.board.to_fen());
+      },
+      Action::Release => self.turn_pressed = false,
+      _ => (),
+    };
+
+    match self.window.get_key(Key::Enter) {
+      Action::Press if !self.done_pressed => {
+        self.done_pressed = true;
+
+        let _ = self.board.set_castling(&editor::possible_castling(&self.board));
+
+        match self.board.validate() {
+          Ok(_) => self.editing = false,
+          Err(err) => {
+            println!("{}", err);
+            self.window.set_title(&format!("Rust Chess - {}", err));
+          },
+        };
+      },
+      Action::Release => self.done_pressed = false,
+      _ => (),
+    };
   }
 
   //
@@ -363,6 +541,29 @@ impl Window {
       self.window.set_should_close(true);
     }
 
+    // Nothing else happens until the promotion piece is picked
+    if self.pending_promotion.is_some() {
+      self.pick_promotion();
+      return;
+    }
+
+    // Setting up a position, there are no moves to take back
+    if self.editing {
+      self.process_editor_keys();
+    }
+
+    // Take back the last move (and the other player's reply)
+    else {
+      match self.window.get_key(Key::Backspace) {
+        Action::Press if !self.undo_pressed => {
+          self.undo_pressed = true;
+          self.gui_sender.send(String::from("undo")).unwrap();
+        },
+        Action::Release => self.undo_pressed = false,
+        _ => (),
+      };
+    }
+
     if self.window.get_key(Key::W) == Action::Press {
       self.camera.process_keyboard(camera::CameraMovement::Forward, 0.1);
     }
@@ -414,26 +615,59 @@ impl Window {
       // Get GL coordinates from the mouse coordinates
       let (x_gl, y_gl) = self.map_window_to_gl(x as i32, y as i32);
 
+      // Black's screen is turned around
+      let my_color = self.board.get_my_color();
+
+      // A piece moved in the editor
+      let mut edit = None;
+
+      // A piece dropped nowhere
+      let mut rebuffer = false;
+
       for model in self.models.iter_mut() {
 
         // Found the chess piece we are dragging
         if model.is_dragging() {
 
           // Where did we come from
-          let current_position = model.board_position();
+          let current_position = Self::orient(&my_color, model.board_position());
 
           // Where we arrived
-          let future_position = model.calculate_board_position(x_gl, y_gl);
+          let future_position = Self::orient(&my_color, model.calculate_board_position(x_gl, y_gl));
+
+          // Setting up a position, not playing
+          if self.editing {
+            edit = Some((current_position, future_position));
+            self.dragging = false;
 
-          // Get those two in the board notation
-          let from = Board::position_to_notation(current_position);
-          let to = Board::position_to_notation(future_position);
+            break;
+          }
 
-          // And construct a valid chess move (e.g. e2e4)
-          let notation = from + &to;
+          // Get those two in the board notation and construct a chess move (e.g. e2e4)
+          let notation = match (Board::position_to_notation(current_position), Board::position_to_notation(future_position)) {
+            (Some(from), Some(to)) => from + &to,
+
+            // Dropped off the board, the piece goes back
+            _ => {
+              self.dragging = false;
+              rebuffer = true;
+
+              break;
+            },
+          };
+
+          // Pawns reaching the last row need to know what they become
+          if self.board.is_promotion(current_position, future_position) {
+            println!("Promote to: (Q)ueen, (R)ook, (B)ishop or k(N)ight?");
+
+            self.window.set_title(promotion_title);
+            self.pending_promotion = Some(notation);
+          }
 
           // Send it to the game thread
-          self.gui_sender.send(notation).unwrap();
+          else {
+            self.gui_sender.send(notation).unwrap();
+          }
 
           // The chess piece is dropped now
           model.dropping(x_gl, y_gl);
@@ -444,6 +678,31 @@ impl Window {
           break;
         }
       }
+
+      if let Some((from, to)) = edit {
+        self.drop_edit(from, to);
+      }
+
+      if rebuffer {
+        self.buffer();
+      }
+    }
+  }
+
+  /// Wait for Q, R, B or N to be pressed and send the promotion to the game thread.
+  fn pick_promotion(&mut self) {
+    let keys = [(Key::Q, "q"), (Key::R, "r"), (Key::B, "b"), (Key::N, "n")];
+
+    for &(key, letter) in keys.iter() {
+      if self.window.get_key(key) == Action::Press {
+        // e.g. e7e8q
+        let notation = self.pending_promotion.take().unwrap() + letter;
+
+        self.gui_sender.send(notation).unwrap();
+        self.window.set_title(window_title);
+
+        break;
+      }
     }
   }
 
@@ -455,7 +714,20 @@ impl Window {
     let x_gl = -1.0 + slope_x * x as f32;
     let y_gl = -(-1.0 + slope_y * y as f32); // Flip the y axis
 
-    (x_gl, y_gl)
+    // The editor squeezes the board to the left, see Self::view()
+    match self.editing {
+      true => ((x_gl + 0.2) / 0.8, y_gl),
+      false => (x_gl, y_gl),
+    }
+  }
+
+  /// The view is unchanged, except in the editor: the window is wider
+  /// and the board takes its left 8/10, the palette the rest.
+  fn view(&self) -> Matrix4<f32> {
+    match self.editing {
+      true => Matrix4::from_translation(Vector3::new(-0.2, 0.0, 0.0)) * Matrix4::from_nonuniform_scale(0.8, 1.0, 1.0),
+      false => <Matrix4<f32> as One>::one(),
+    }
   }
 
   /// Draw the game
@@ -468,8 +740,7 @@ impl Window {
 
     self.process_events();
 
-    // The view is unchanged.
-    self.set_mat4("view", <Matrix4<f32> as One>::one());
+    self.set_mat4("view", self.view());
 
     // Draw all the models.
     for model in &self.models {
@@ -508,4 +779,27 @@ mod tests {
     // assert_eq!(x, 0.0);
     // assert_eq!(y, 0.0);
   }
+
+  #[test]
+  fn test_orient() {
+    // White sees the board as it is
+    assert_eq!(Window::orient(&Color::White, (4, 6)), (4, 6));
+
+    // Black sees it turned around, and turning it back gives the square again
+    assert_eq!(Window::orient(&Color::Black, (4, 6)), (3, 1));
+    assert_eq!(Window::orient(&Color::Black, Window::orient(&Color::Black, (4, 6))), (4, 6));
+
+    // Dropped off the board
+    assert_eq!(Window::orient(&Color::Black, (8, 2)), (8, 2));
+  }
+
+  #[test]
+  fn test_palette_piece() {
+    assert_eq!(Window::palette_piece((8, 0)), Some(Piece{kind: PieceKind::King, color: Color::White}));
+    assert_eq!(Window::palette_piece((9, 5)), Some(Piece{kind: PieceKind::Pawn, color: Color::Black}));
+
+    // Below the palette, or on the board
+    assert_eq!(Window::palette_piece((9, 6)), None);
+    assert_eq!(Window::palette_piece((3, 0)), None);
+  }
 }
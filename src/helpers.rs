@@ -1,10 +1,25 @@
 use std::io::{stdin, stdout, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 
 pub fn input() -> String {
+  read_input().unwrap_or_default()
+}
+
+/// Read a line from stdin, without the new line.
+///
+/// Return: Option<String> (None when there is nothing left to read,
+/// or stdin can't be read, e.g. it isn't UTF-8)
+pub fn read_input() -> Option<String> {
   // Flush stdout
   let _ = stdout().flush();
   let mut input = String::new();
-  stdin().read_line(&mut input).expect("read_line");
+
+  match stdin().read_line(&mut input) {
+    Ok(0) | Err(_) => return None,
+    Ok(_) => (),
+  };
 
   // Remove trailing new line chars
   if let Some('\n') = input.chars().next_back() {
@@ -15,5 +30,58 @@ pub fn input() -> String {
     input.pop();
   }
 
-  input
-}
\ No newline at end of file
+  Some(input)
+}
+
+/// Seconds since 1970-01-01 (UTC).
+pub fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|elapsed| elapsed.as_secs())
+    .unwrap_or(0)
+}
+
+/// Heads or tails, e.g. for who plays white.
+/// Every RandomState is seeded differently, which is random enough for that.
+pub fn coin_flip() -> bool {
+  let mut hasher = RandomState::new().build_hasher();
+  hasher.write_u64(now());
+
+  hasher.finish() & 1 == 1
+}
+
+/// Today's date (UTC) as YYYY.MM.DD, the way PGN wants it.
+pub fn today() -> String {
+  let (year, month, day) = civil_from_days((now() / 86_400) as i64);
+
+  format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) date.
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let z = days + 719_468;
+  let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+  let doe = z - era * 146_097;
+  let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+  (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_civil_from_days() {
+    assert_eq!(civil_from_days(0), (1970, 1, 1));
+    assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    assert_eq!(civil_from_days(20_743), (2026, 10, 17));
+    assert_eq!(civil_from_days(-1), (1969, 12, 31));
+  }
+}
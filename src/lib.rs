@@ -18,34 +18,160 @@ extern crate serde;
 extern crate serde_json;
 
 // Graphics
+#[cfg(feature = "gui")]
 extern crate gl;
+#[cfg(feature = "gui")]
 extern crate glfw;
+#[cfg(feature = "gui")]
 extern crate cgmath;
 
+#[cfg(feature = "gui")]
 extern crate tobj;
 
 // HTTP
 extern crate reqwest;
 
 // Modules
+pub mod bitboard;
+pub mod zobrist;
 pub mod board;
+pub mod error;
+pub mod san;
+pub mod pgn;
+pub mod save;
+pub mod clock;
+pub mod engine;
+pub mod editor;
+pub mod cli;
 pub mod game;
 pub mod client;
 pub mod protocol;
 pub mod connection;
 pub mod helpers;
+
+// The window
+#[cfg(feature = "gui")]
 pub mod graphic_object;
+#[cfg(feature = "gui")]
 pub mod model_loader;
+#[cfg(feature = "gui")]
 pub mod camera;
+#[cfg(feature = "gui")]
 pub mod gui;
 
+#[cfg(feature = "gui")]
 pub mod models;
 
-use game::Game;
+use game::{Game, Settings};
+use board::{Board, Color};
+use error::ChessError;
+
+use std::time::Instant;
+
+/// Play a game.
+///
+/// Parameters:
+/// `settings`: &Settings, who to play, which side, the clock and so on
+/// `headless`: bool, play in the terminal without opening a window
+#[cfg(feature = "gui")]
+pub fn init(settings: &Settings, headless: bool) {
+  match headless {
+    true => Game::start_headless(settings),
+    false => Game::start(settings),
+  };
+}
+
+/// Play a game in the terminal, this build has no window.
+#[cfg(not(feature = "gui"))]
+pub fn init(settings: &Settings, _headless: bool) {
+  Game::start_headless(settings);
+}
+
+/// Convert a game between PGN and the save format, by the file extensions
+/// (.pgn for PGN, anything else is a saved game).
+///
+/// Parameters:
+/// `from`: &str, the file to read
+/// `to`: &str, the file to write
+/// `index`: usize, which game in a PGN file, from 0
+///
+/// Return: Result<(), ChessError>
+pub fn convert(from: &str, to: &str, index: usize) -> Result<(), ChessError> {
+  let mut game = Game::new(Color::White);
+
+  match from.to_lowercase().ends_with(".pgn") {
+    true => game.load_pgn(from, index)?,
+    false => game.load(from)?,
+  };
+
+  match to.to_lowercase().ends_with(".pgn") {
+    true => game.save_pgn(to),
+    false => game.save(to),
+  }
+}
+
+/// Print perft numbers for every first move and in total.
+///
+/// Parameters:
+/// `depth`: usize
+/// `fen`: &str, the position to start from
+pub fn perft(depth: usize, fen: &str) {
+  let board = match Board::from_fen(fen, Color::White) {
+    Ok(board) => board,
+    Err(err) => {
+      println!("{}", err);
+      return;
+    },
+  };
+
+  let start = Instant::now();
+  let divide = board.divide(depth);
+  let elapsed = start.elapsed();
+
+  for &(ref m, nodes) in divide.iter() {
+    println!("{}: {}", m, nodes);
+  }
+
+  let nodes: u64 = divide.iter().map(|&(_, nodes)| nodes).sum();
+  let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+
+  println!("\r\nNodes searched: {}", nodes);
+  println!("Time: {:.3}s ({:.0} nodes/s)", seconds, nodes as f64 / seconds);
+}
+
+/// Print the line the engine expects, in algebraic notation, and its score
+/// for the side to move.
+///
+/// Parameters:
+/// `fen`: &str, the position to analyze
+/// `depth`: usize, plies to look ahead
+pub fn analyze(fen: &str, depth: usize) {
+  let mut board = match Board::from_fen(fen, Color::White) {
+    Ok(board) => board,
+    Err(err) => {
+      println!("{}", err);
+      return;
+    },
+  };
+
+  let (line, score) = match engine::analyze(&board, depth) {
+    Some(analysis) => analysis,
+    None => {
+      println!("No moves, the game is over.");
+      return;
+    },
+  };
+
+  let mut moves = Vec::new();
+
+  for m in line.iter() {
+    moves.push(san::to_san(&board, m));
 
-pub fn init() {
-  // let mut game = Game::new(Color::White);
+    if board.play(m, true).is_err() {
+      break;
+    }
+  }
 
-  // game.start();
-  Game::start();
+  println!("Best line: {}", moves.join(" "));
+  println!("Score: {} (depth {})", engine::describe_score(score), depth);
 }
@@ -5,9 +5,44 @@
 ///
 extern crate rust_chess;
 
-use rust_chess::init;
+use rust_chess::{init, convert, perft, analyze};
+use rust_chess::cli::{self, Command, USAGE};
+
+use std::env;
+use std::process;
 
 // Let's do this
 fn main() {
-    init();
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(err) => {
+            println!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        },
+    };
+
+    match command {
+        Command::Play{settings, headless} => init(&settings, headless),
+
+        // rust_chess convert <from> <to> [--game N]
+        Command::Convert{from, to, game} => {
+            match convert(&from, &to, game - 1) {
+                Ok(_) => println!("Wrote {}.", to),
+                Err(err) => {
+                    println!("Could not convert {}: {}", from, err);
+                    process::exit(1);
+                },
+            };
+        },
+
+        // rust_chess perft <depth> [fen]
+        Command::Perft{depth, fen} => perft(depth, &fen),
+
+        // rust_chess analyze [fen] [--depth N]
+        Command::Analyze{fen, depth} => analyze(&fen, depth),
+
+        Command::Help => println!("{}", USAGE),
+    }
 }
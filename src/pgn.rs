@@ -0,0 +1,411 @@
+/// Portable Game Notation, the format other chess programs read and write.
+
+use board::{Color, STARTING_FEN};
+use error::ChessError;
+use game::{GameStatus, HistoryEntry};
+
+/// Movetext lines are wrapped at this length, as the PGN spec recommends.
+const LINE_LENGTH: usize = 79;
+
+/// The result tag and movetext ending for a game.
+///
+/// Parameters:
+/// `status`: &GameStatus
+/// `turn`: &Color, the side to move
+///
+/// Return: &'static str (1-0, 0-1, 1/2-1/2 or * while the game goes on)
+pub fn result(status: &GameStatus, turn: &Color) -> &'static str {
+  match (status, turn) {
+    // The side to move lost
+    (&GameStatus::Checkmate, &Color::Black) | (&GameStatus::TimeForfeit, &Color::Black) => "1-0",
+    (&GameStatus::Checkmate, _) | (&GameStatus::TimeForfeit, _) => "0-1",
+    (status, _) if status.is_draw() => "1/2-1/2",
+    _ => "*",
+  }
+}
+
+/// Write a game in PGN export format.
+///
+/// The Seven Tag Roster comes first, in order. Games that didn't start
+/// from the usual position also get the SetUp and FEN tags.
+///
+/// Parameters:
+/// `tags`: &[(&str, String)], name and value of the Seven Tag Roster and any others
+/// `start_fen`: &str, the position before the first move
+/// `moves`: &[HistoryEntry]
+/// `result`: &str, see result()
+///
+/// Return: String
+pub fn write(tags: &[(&str, String)], start_fen: &str, moves: &[HistoryEntry], result: &str) -> String {
+  let mut pgn = String::new();
+
+  for &(name, ref value) in tags.iter() {
+    pgn.push_str(&tag(name, value));
+  }
+
+  if start_fen != STARTING_FEN {
+    pgn.push_str(&tag("SetUp", "1"));
+    pgn.push_str(&tag("FEN", start_fen));
+  }
+
+  pgn.push('\n');
+
+  // Movetext, e.g. 1. e4 e5 2. Nf3, or 12... Qd7 when black moves first
+  let mut tokens = Vec::new();
+
+  for (ply, entry) in moves.iter().enumerate() {
+    let fen: Vec<&str> = entry.fen_before.split(' ').collect();
+    let number = fen.get(5).unwrap_or(&"1");
+
+    match fen.get(1) {
+      Some(&"b") if ply == 0 => tokens.push(format!("{}...", number)),
+      Some(&"b") => (),
+      _ => tokens.push(format!("{}.", number)),
+    };
+
+    tokens.push(entry.san.clone());
+  }
+
+  tokens.push(String::from(result));
+
+  let mut line = String::new();
+
+  for token in tokens {
+    if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+      pgn.push_str(&line);
+      pgn.push('\n');
+      line.clear();
+    }
+
+    if !line.is_empty() {
+      line.push(' ');
+    }
+
+    line.push_str(&token);
+  }
+
+  pgn.push_str(&line);
+  pgn.push_str("\n\n");
+
+  pgn
+}
+
+/// A game read from a PGN file: its tags and the moves of the main line.
+///
+/// Parameters:
+/// `tags`: Vec<(String, String)>, name and value, in file order
+/// `moves`: Vec<String>, in algebraic notation, variations left out
+/// `result`: String, 1-0, 0-1, 1/2-1/2 or *
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PgnGame {
+  pub tags: Vec<(String, String)>,
+  pub moves: Vec<String>,
+  pub result: String,
+}
+
+impl PgnGame {
+  /// Value of a tag, e.g. tag("White").
+  pub fn tag(&self, name: &str) -> Option<&str> {
+    self.tags.iter()
+      .find(|&&(ref tag, _)| tag == name)
+      .map(|&(_, ref value)| value.as_str())
+  }
+
+  /// Nothing was read into this game yet.
+  fn is_empty(&self) -> bool {
+    self.tags.is_empty() && self.moves.is_empty()
+  }
+}
+
+/// Read every game in a PGN file. Comments, NAGs ($1) and
+/// variations are skipped, only the main line is kept.
+///
+/// Parameters:
+/// `text`: &str, the contents of the PGN file
+///
+/// Return: Result<Vec<PgnGame>, ChessError> (a parse error for broken tags, comments or variations)
+pub fn read(text: &str) -> Result<Vec<PgnGame>, ChessError> {
+  let mut games = Vec::new();
+  let mut game = PgnGame::default();
+
+  // How deep in (variations) we are
+  let mut depth = 0;
+
+  let chars: Vec<char> = text.chars().collect();
+  let mut i = 0;
+
+  while i < chars.len() {
+    let c = chars[i];
+    let line_start = i == 0 || chars[i - 1] == '\n';
+
+    // Escaped lines, for other programs
+    if c == '%' && line_start {
+      i = skip_to(&chars, i, '\n');
+    }
+
+    // Rest of line comment
+    else if c == ';' {
+      i = skip_to(&chars, i, '\n');
+    }
+
+    // Brace comment, can't be nested
+    else if c == '{' {
+      i = skip_to(&chars, i, '}');
+
+      if i > chars.len() {
+        return Err(ChessError::Parse(String::from("PGN comment is missing its closing '}'.")));
+      }
+    }
+
+    else if c == '(' {
+      depth += 1;
+      i += 1;
+    }
+
+    else if c == ')' {
+      if depth == 0 {
+        return Err(ChessError::Parse(String::from("PGN variation closed with ')' but never opened.")));
+      }
+
+      depth -= 1;
+      i += 1;
+    }
+
+    // Tag pair, e.g. [White "Lev"]
+    else if c == '[' && depth == 0 {
+      // Tags after moves start the next game, even if the result is missing
+      if !game.moves.is_empty() {
+        game.result = String::from("*");
+        games.push(game);
+        game = PgnGame::default();
+      }
+
+      let (tag, next) = read_tag(&chars, i)?;
+
+      game.tags.push(tag);
+      i = next;
+    }
+
+    else if c.is_whitespace() {
+      i += 1;
+    }
+
+    // Symbols: moves, move numbers, results and NAGs
+    else {
+      let start = i;
+
+      while i < chars.len() && !chars[i].is_whitespace() && !"{}()[];".contains(chars[i]) {
+        i += 1;
+      }
+
+      // A bracket that can't go here, e.g. a stray '}' or a tag inside a variation
+      if i == start {
+        if depth == 0 {
+          return Err(ChessError::Parse(format!("PGN has a stray '{}'.", c)));
+        }
+
+        i += 1;
+        continue;
+      }
+
+      let token: String = chars[start..i].iter().collect();
+
+      // Moves in variations don't count
+      if depth > 0 {
+        continue;
+      }
+
+      match token.as_str() {
+        "1-0" | "0-1" | "1/2-1/2" | "*" => {
+          game.result = token;
+          games.push(game);
+          game = PgnGame::default();
+        },
+
+        _ => {
+          if let Some(m) = move_from_token(&token) {
+            game.moves.push(m);
+          }
+        },
+      };
+    }
+  }
+
+  if depth > 0 {
+    return Err(ChessError::Parse(String::from("PGN variation is missing its closing ')'.")));
+  }
+
+  // The last game may not have a result
+  if !game.is_empty() {
+    game.result = String::from("*");
+    games.push(game);
+  }
+
+  Ok(games)
+}
+
+/// Index right after the next `end` character, or past the end of the text if there is none.
+fn skip_to(chars: &[char], start: usize, end: char) -> usize {
+  match chars[start + 1..].iter().position(|&c| c == end) {
+    Some(offset) => start + 1 + offset + 1,
+    None => chars.len() + 1,
+  }
+}
+
+/// Read a tag pair starting at the '['.
+///
+/// Return: Result<((String, String), usize), ChessError> (the tag and the index after the ']')
+fn read_tag(chars: &[char], start: usize) -> Result<((String, String), usize), ChessError> {
+  let mut i = start + 1;
+  let mut name = String::new();
+
+  while i < chars.len() && chars[i].is_whitespace() {
+    i += 1;
+  }
+
+  while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+    name.push(chars[i]);
+    i += 1;
+  }
+
+  while i < chars.len() && chars[i].is_whitespace() {
+    i += 1;
+  }
+
+  if name.is_empty() || i >= chars.len() || chars[i] != '"' {
+    return Err(ChessError::Parse(format!("PGN tag '{}' needs a name and a quoted value.", name)));
+  }
+
+  // Quoted value, with \" and \\ escapes
+  let mut value = String::new();
+  i += 1;
+
+  loop {
+    match chars.get(i) {
+      Some(&'\\') => {
+        if let Some(&escaped) = chars.get(i + 1) {
+          value.push(escaped);
+        }
+
+        i += 2;
+      },
+
+      Some(&'"') => break,
+      Some(&c) => { value.push(c); i += 1; },
+      None => return Err(ChessError::Parse(format!("PGN tag '{}' is missing its closing quote.", name))),
+    };
+  }
+
+  i += 1;
+
+  while i < chars.len() && chars[i].is_whitespace() {
+    i += 1;
+  }
+
+  if i >= chars.len() || chars[i] != ']' {
+    return Err(ChessError::Parse(format!("PGN tag '{}' is missing its closing ']'.", name)));
+  }
+
+  Ok(((name, value), i + 1))
+}
+
+/// The move in a movetext token, without the move number in front (12.e4)
+/// or annotations after it (e4!?). None for move numbers, NAGs and annotations.
+fn move_from_token(token: &str) -> Option<String> {
+  // Castling with zeros looks like a move number
+  let token = match token.starts_with("0-0") {
+    true => token,
+    false => token.trim_start_matches(|c: char| c.is_ascii_digit()).trim_start_matches('.'),
+  };
+
+  let token = token.trim_end_matches(|c| c == '!' || c == '?');
+
+  if token.is_empty() || token.starts_with('$') {
+    None
+  }
+
+  else {
+    Some(String::from(token))
+  }
+}
+
+/// A tag pair, e.g. [White "Lev"], with quotes and backslashes escaped.
+fn tag(name: &str, value: &str) -> String {
+  let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+
+  format!("[{} \"{}\"]\n", name, value)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_read() {
+    let text = r#"
+% Exported by some program
+[Event "Paris"]
+[White "Paul \"The Pride\" Morphy"]
+[Black "Duke Karl / Count Isouard"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 d6 {This is the Philidor Defence} 3. d4 Bg4?! $6 4.dxe5 Bxf3 (4... dxe5
+5. Qxd8+ (5. Nxe5 Qa5+) Kxd8 {and black can't castle}) 5. Qxf3 dxe5 ; rest of line
+6. Bc4 Nf6 7. Qb3 Qe7 8. Nc3 c6 9. Bg5 b5 10. Nxb5 cxb5 11. Bxb5+ Nbd7 12. O-O-O
+Rd8 13. Rxd7 Rxd7 14. Rd1 Qe6 15. Bxd7+ Nxd7 16. Qb8+ Nxb8 17. Rd8# 1-0
+
+[Event "Short"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/8/R3K3 b - - 10 40"]
+
+40... Kd7 41. Ra7+ 0-0 *
+
+1. d4 d5
+"#;
+
+    let games = read(text).unwrap();
+
+    assert_eq!(games.len(), 3);
+
+    assert_eq!(games[0].tag("White"), Some("Paul \"The Pride\" Morphy"));
+    assert_eq!(games[0].tag("Round"), None);
+    assert_eq!(games[0].result, "1-0");
+    assert_eq!(games[0].moves.len(), 33);
+    assert_eq!(&games[0].moves[..8], &["e4", "e5", "Nf3", "d6", "d4", "Bg4", "dxe5", "Bxf3"]);
+    assert_eq!(games[0].moves[8], "Qxf3");
+    assert_eq!(games[0].moves[32], "Rd8#");
+
+    assert_eq!(games[1].tag("FEN"), Some("4k3/8/8/8/8/8/8/R3K3 b - - 10 40"));
+    assert_eq!(games[1].moves, vec!["Kd7", "Ra7+", "0-0"]);
+    assert_eq!(games[1].result, "*");
+
+    // No tags and no result
+    assert_eq!(games[2].moves, vec!["d4", "d5"]);
+    assert_eq!(games[2].result, "*");
+  }
+
+  #[test]
+  fn test_read_errors() {
+    let broken = [
+      "1. e4 { never closed",
+      "1. e4 (1. d4",
+      "1. e4 ) e5",
+      "[White Lev]",
+      "[White \"Lev]",
+      "[White \"Lev\"",
+      "1. e4 } e5",
+      "1. e4 ] e5",
+    ];
+
+    for text in broken.iter() {
+      match read(text) {
+        Err(ChessError::Parse(_)) => (),
+        other => panic!("{}: {:?}", text, other),
+      };
+    }
+
+    // Brackets inside a variation are skipped with the rest of it
+    let games = read("1. e4 ( [x] ) e5").unwrap();
+    assert_eq!(games[0].moves, vec!["e4", "e5"]);
+  }
+}
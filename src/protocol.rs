@@ -1,5 +1,10 @@
 // Display
 use std::fmt;
+use std::str::FromStr;
+
+// Colors
+use board::Color;
+use helpers;
 
 /// Message headers
 #[derive(Debug, PartialEq)]
@@ -8,6 +13,9 @@ pub enum Message {
   Bye,
   BadMessage,
   MakeMove,
+  Undo,
+  Clock,
+  Color,
 }
 
 impl fmt::Display for Message {
@@ -18,6 +26,9 @@ impl fmt::Display for Message {
       Message::Bye => write!(f, "bye"),
       Message::BadMessage => write!(f, "bad_msg"),
       Message::MakeMove => write!(f, "make_move"),
+      Message::Undo => write!(f, "undo"),
+      Message::Clock => write!(f, "clock"),
+      Message::Color => write!(f, "color"),
     };
 
     Ok(())
@@ -31,6 +42,9 @@ pub enum MessageRegex {
   Bye,
   BadMessage,
   MakeMove,
+  Undo,
+  Clock,
+  Color,
 }
 
 impl fmt::Display for MessageRegex {
@@ -40,9 +54,111 @@ impl fmt::Display for MessageRegex {
       MessageRegex::Hello => write!(f, r"{} [A-Za-z0-9]+", Message::Hello),
       MessageRegex::Bye => write!(f, r"{}$", Message::Bye),
       MessageRegex::BadMessage => write!(f, r"{}$", Message::BadMessage),
-      MessageRegex::MakeMove => write!(f, r"{} [A-Ha-h][1-8][A-Ha-h][1-8]$", Message::MakeMove),
+      MessageRegex::MakeMove => write!(
+        f,
+        r"{} ([A-Ha-h][1-8][A-Ha-h][1-8][QRBNqrbn]?|[Oo0]-[Oo0](-[Oo0])?[+#]?|[NBRQK]?[a-h]?[1-8]?x?[a-h][1-8](=?[NBRQ])?[+#]?)$",
+        Message::MakeMove
+      ),
+      MessageRegex::Undo => write!(f, r"^{}$", Message::Undo),
+      MessageRegex::Clock => write!(f, r"^{} [0-9]+ [0-9]+$", Message::Clock),
+      MessageRegex::Color => write!(f, r"^{} (white|black|random|any)$", Message::Color),
     };
 
     Ok(())
   }
-}
\ No newline at end of file
+}
+/// The side a player asks for before the game starts.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColorChoice {
+  White,
+  Black,
+  Random,
+}
+
+impl ColorChoice {
+  /// The color to play, flipping a coin for Random.
+  ///
+  /// Return: Color (White or Black)
+  pub fn resolve(&self) -> Color {
+    match *self {
+      ColorChoice::White => Color::White,
+      ColorChoice::Black => Color::Black,
+      ColorChoice::Random => match helpers::coin_flip() {
+        true => Color::White,
+        false => Color::Black,
+      },
+    }
+  }
+}
+
+impl fmt::Display for ColorChoice {
+  //
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      ColorChoice::White => write!(f, "white"),
+      ColorChoice::Black => write!(f, "black"),
+      ColorChoice::Random => write!(f, "random"),
+    }
+  }
+}
+
+impl FromStr for ColorChoice {
+  type Err = String;
+
+  /// e.g. white, b or random
+  fn from_str(value: &str) -> Result<ColorChoice, String> {
+    match value.to_lowercase().as_str() {
+      "white" | "w" => Ok(ColorChoice::White),
+      "black" | "b" => Ok(ColorChoice::Black),
+      "random" | "r" => Ok(ColorChoice::Random),
+      _ => Err(format!("{} is not a color, try white, black or random.", value)),
+    }
+  }
+}
+
+/// The host decides who plays what.
+/// The host's own choice comes first, then whatever the guest asked for.
+///
+/// Parameters:
+/// `host`: Option<&ColorChoice>, None if the host doesn't mind
+/// `guest`: &str, what the guest asked for: white, black, random or any
+///
+/// Return: Color (the host's color, the guest gets the other one)
+pub fn host_color(host: Option<&ColorChoice>, guest: &str) -> Color {
+  match host {
+    Some(choice) => choice.resolve(),
+
+    None => match guest.parse::<ColorChoice>() {
+      Ok(choice) => choice.resolve().opposite(),
+
+      // Nobody minds, whoever hosts moves first
+      Err(_) => Color::White,
+    },
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_color_choice() {
+    for choice in [ColorChoice::White, ColorChoice::Black, ColorChoice::Random].iter() {
+      assert_eq!(choice.to_string().parse::<ColorChoice>().as_ref(), Ok(choice));
+    }
+
+    assert_eq!("B".parse::<ColorChoice>(), Ok(ColorChoice::Black));
+    assert!("green".parse::<ColorChoice>().is_err());
+    assert!(ColorChoice::Random.resolve() != Color::Nil);
+
+    // The host's choice wins
+    assert_eq!(host_color(Some(&ColorChoice::Black), "black"), Color::Black);
+    assert_eq!(host_color(Some(&ColorChoice::White), "any"), Color::White);
+
+    // Otherwise the guest gets what they asked for
+    assert_eq!(host_color(None, "white"), Color::Black);
+    assert_eq!(host_color(None, "black"), Color::White);
+    assert_eq!(host_color(None, "any"), Color::White);
+    assert!(host_color(None, "random") != Color::Nil);
+  }
+}
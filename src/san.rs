@@ -0,0 +1,262 @@
+/// Standard Algebraic Notation, e.g. Nf3, exd5, O-O or e8=Q+
+
+// Regex
+extern crate regex;
+use regex::Regex;
+
+use board::{Board, Move, MoveKind, PieceKind, Square};
+use error::ChessError;
+
+/// Describe a legal move in SAN, including the check (+) or mate (#) suffix.
+///
+/// Parameters:
+/// `board`: &Board, the position before the move
+/// `m`: &Move, one of board.legal_moves()
+///
+/// Return: String
+pub fn to_san(board: &Board, m: &Move) -> String {
+  let mut san = String::new();
+
+  let kind = match board.piece_at(m.from) {
+    Some(piece) => piece.kind,
+    None => PieceKind::Nil,
+  };
+
+  // Castling is the same for both sides
+  if m.kind == MoveKind::Castling {
+    san.push_str(match m.to.file() > m.from.file() {
+      true => "O-O",
+      false => "O-O-O",
+    });
+  }
+
+  else {
+    let captures = m.kind == MoveKind::Capture || m.kind == MoveKind::EnPassant;
+
+    if kind == PieceKind::Pawn {
+      // Pawns are only named when they capture, by their file
+      if captures {
+        san.push_str(&m.from.to_string()[..1]);
+      }
+    }
+
+    else {
+      san.push_str(&kind.to_string());
+      san.push_str(&disambiguate(board, m, &kind));
+    }
+
+    if captures {
+      san.push('x');
+    }
+
+    san.push_str(&m.to.to_string());
+
+    if let Some(ref promotion) = m.promotion {
+      san.push('=');
+      san.push_str(&promotion.to_string());
+    }
+  }
+
+  // Check or mate
+  let mut after = board.clone();
+
+  if after.play(m, true).is_ok() {
+    let turn = after.get_turn();
+
+    if after.is_in_check(&turn) {
+      san.push(match after.has_legal_moves(&turn) {
+        true => '+',
+        false => '#',
+      });
+    }
+  }
+
+  san
+}
+
+/// Find the legal move a SAN string describes. Check and mate suffixes
+/// and annotations (!, ?) are optional, and castling can be written with zeros.
+///
+/// Parameters:
+/// `board`: &Board, the position to move in
+/// `san`: &str, e.g. Nbd7, exd6 or e8=Q
+///
+/// Return: Result<Move, ChessError> (bad notation if no move, or more than one, matches)
+pub fn parse_san(board: &Board, san: &str) -> Result<Move, ChessError> {
+  lazy_static! {
+    static ref RE: Regex = Regex::new(
+      "^([NBRQK])?([a-h])?([1-8])?(x)?([a-h][1-8])(=?([NBRQ]))?$"
+    ).unwrap();
+  }
+
+  let trimmed = san.trim().trim_end_matches(|c| c == '+' || c == '#' || c == '!' || c == '?');
+  let moves = board.legal_moves();
+
+  let candidates: Vec<&Move> = match trimmed.replace("0", "O").as_str() {
+    // Castling
+    "O-O" | "O-O-O" => {
+      let king_side = trimmed.len() == 3;
+
+      moves.iter()
+        .filter(|m| m.kind == MoveKind::Castling && (m.to.file() > m.from.file()) == king_side)
+        .collect()
+    },
+
+    _ => {
+      let captures = match RE.captures(trimmed) {
+        Some(captures) => captures,
+        None => return Err(ChessError::BadNotation(format!("'{}' is not a move in algebraic notation, e.g. Nf3 or exd5.", san))),
+      };
+
+      let kind = match captures.get(1) {
+        Some(letter) => letter.as_str().parse::<PieceKind>()?,
+        None => PieceKind::Pawn,
+      };
+
+      let from_file = captures.get(2).map(|file| file.as_str().as_bytes()[0] - b'a');
+      let from_rank = captures.get(3).map(|rank| rank.as_str().as_bytes()[0] - b'1');
+      let to = captures[5].parse::<Square>()?;
+
+      let promotion = match captures.get(7) {
+        Some(letter) => Some(letter.as_str().parse::<PieceKind>()?),
+        None => None,
+      };
+
+      moves.iter()
+        .filter(|m| {
+          let piece = board.piece_at(m.from).map(|piece| piece.kind);
+
+          piece == Some(kind.clone()) && m.to == to && m.promotion == promotion &&
+            from_file.map_or(true, |file| m.from.file() == file as usize) &&
+            from_rank.map_or(true, |rank| m.from.rank() == rank as usize)
+        })
+        .collect()
+    },
+  };
+
+  match candidates.len() {
+    1 => Ok(candidates[0].clone()),
+    0 => Err(ChessError::BadNotation(format!("No legal move matches '{}'.", san))),
+    _ => Err(ChessError::BadNotation(format!("'{}' is ambiguous, add the file or rank the piece comes from.", san))),
+  }
+}
+
+/// File, rank or both of the moving piece, if another piece of the same
+/// kind can go to the same square.
+fn disambiguate(board: &Board, m: &Move, kind: &PieceKind) -> String {
+  let others: Vec<Move> = board.legal_moves().into_iter()
+    .filter(|other| {
+      other.to == m.to && other.from != m.from &&
+        board.piece_at(other.from).map(|piece| piece.kind) == Some(kind.clone())
+    })
+    .collect();
+
+  let from = m.from.to_string();
+
+  if others.is_empty() {
+    String::new()
+  }
+
+  else if others.iter().all(|other| other.from.file() != m.from.file()) {
+    from[..1].to_string()
+  }
+
+  else if others.iter().all(|other| other.from.rank() != m.from.rank()) {
+    from[1..].to_string()
+  }
+
+  else {
+    from
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use board::Color;
+
+  /// SAN of every legal move in the position.
+  fn all_san(fen: &str) -> Vec<String> {
+    let board = Board::from_fen(fen, Color::White).unwrap();
+
+    board.legal_moves().iter().map(|m| to_san(&board, m)).collect()
+  }
+
+  #[test]
+  fn test_to_san() {
+    let moves = all_san(::board::STARTING_FEN);
+
+    assert!(moves.contains(&String::from("e4")));
+    assert!(moves.contains(&String::from("Nf3")));
+    assert_eq!(moves.len(), 20);
+
+    // Captures, en passant, promotions and checks
+    let moves = all_san("1n2k3/P7/8/3pP3/8/8/8/4K3 w - d6 0 1");
+
+    assert!(moves.contains(&String::from("exd6")));
+    assert!(moves.contains(&String::from("a8=Q"))); // The knight on b8 blocks the check
+    assert!(moves.contains(&String::from("axb8=N")));
+
+    // Castling
+    let moves = all_san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+
+    assert!(moves.contains(&String::from("O-O")));
+    assert!(moves.contains(&String::from("O-O-O")));
+    assert!(moves.contains(&String::from("Rxa8+")));
+
+    // Mate
+    let moves = all_san("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+
+    assert!(moves.contains(&String::from("Ra8#")));
+  }
+
+  #[test]
+  fn test_disambiguation() {
+    // Knights on b1 and f3 (file), rooks on a1 and a5 (rank), queens on h4, e4 and h1 (both)
+    let moves = all_san("8/2k5/8/R7/4Q2Q/5N2/8/RN1K3Q w - - 0 1");
+
+    assert!(moves.contains(&String::from("Nbd2")));
+    assert!(moves.contains(&String::from("Nfd2")));
+    assert!(moves.contains(&String::from("R1a3")));
+    assert!(moves.contains(&String::from("R5a3")));
+    assert!(moves.contains(&String::from("Qh4e1")));
+    assert!(moves.contains(&String::from("Qee1")));
+    assert!(moves.contains(&String::from("Q1e1")));
+  }
+
+  #[test]
+  fn test_parse_san() {
+    let board = Board::from_fen("8/2k5/8/R7/4Q2Q/5N2/8/RN1K3Q w - - 0 1", Color::White).unwrap();
+
+    assert_eq!(parse_san(&board, "Nbd2").unwrap().to_string(), "b1d2");
+    assert_eq!(parse_san(&board, "R5a3").unwrap().to_string(), "a5a3");
+    assert_eq!(parse_san(&board, "Qh4e1").unwrap().to_string(), "h4e1");
+    assert!(parse_san(&board, "Nd2").is_err()); // Ambiguous
+    assert!(parse_san(&board, "Nc4").is_err()); // No knight gets there
+    assert!(parse_san(&board, "hello").is_err());
+
+    let board = Board::from_fen("1n2k3/P7/8/3pP3/8/8/8/4K3 w - d6 0 1", Color::White).unwrap();
+
+    assert_eq!(parse_san(&board, "exd6").unwrap().kind, MoveKind::EnPassant);
+    assert_eq!(parse_san(&board, "a8=Q+").unwrap().to_string(), "a7a8q");
+    assert_eq!(parse_san(&board, "axb8N").unwrap().to_string(), "a7b8n");
+    assert!(parse_san(&board, "a8").is_err()); // Must promote
+
+    let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", Color::White).unwrap();
+
+    assert_eq!(parse_san(&board, "O-O").unwrap().to_string(), "e8g8");
+    assert_eq!(parse_san(&board, "0-0-0").unwrap().to_string(), "e8c8");
+  }
+
+  #[test]
+  fn test_round_trip() {
+    // Kiwipete has a bit of everything
+    let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", Color::White).unwrap();
+
+    for m in board.legal_moves() {
+      let san = to_san(&board, &m);
+
+      assert_eq!(parse_san(&board, &san).unwrap(), m, "{}", san);
+    }
+  }
+}
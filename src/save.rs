@@ -0,0 +1,194 @@
+/// Save files written by Game::save() and read by Game::load().
+///
+/// The format only uses FEN and coordinate moves, so it doesn't change when
+/// the board does. Every file says which version of the format it is:
+///
+/// ```json
+/// {
+///   "version": 2,
+///   "created_at": 1792224000,
+///   "saved_at": 1792227600,
+///   "date": "2026.10.17",
+///   "players": {"white": "Lev", "black": "?"},
+///   "my_color": "White",
+///   "start_fen": "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+///   "fen": "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+///   "moves": [{"move": "e2e4", "san": "e4"}]
+/// }
+/// ```
+///
+/// Timed games also have the clock, with the time left in milliseconds:
+/// `"clock": {"time_control": "40/90+30,30+30", "white_ms": 5400000, "black_ms": 5391500,
+/// "white_moves": 1, "black_moves": 0}`.
+///
+/// `created_at` and `saved_at` are seconds since 1970-01-01 (UTC). Loading replays
+/// `moves` from `start_fen`, so a file with an illegal move is rejected. `fen` is
+/// the position after the last move, for programs that don't want to replay.
+///
+/// Older files are migrated when they are read:
+/// version 0 is the bare board from the first releases,
+/// version 1 is that board with the move history next to it.
+/// Neither has a version field.
+
+// Serialization
+extern crate serde_json;
+use serde_json::Value;
+
+use board::{Board, Color};
+use error::ChessError;
+use game::HistoryEntry;
+use helpers;
+
+/// The version written by this release.
+pub const SAVE_VERSION: u64 = 2;
+
+/// Who plays which side.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Players {
+  pub white: String,
+  pub black: String,
+}
+
+/// A move in the save file, in coordinates (e2e4, e7e8q) and algebraic notation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedMove {
+  #[serde(rename = "move")]
+  pub coordinates: String,
+  pub san: String,
+}
+
+/// The clock of a timed game, see clock::TimeControl for the time control.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedClock {
+  pub time_control: String,
+  pub white_ms: u64,
+  pub black_ms: u64,
+  pub white_moves: u32,
+  pub black_moves: u32,
+}
+
+/// The current save file format, see the module documentation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SaveFile {
+  pub version: u64,
+  pub created_at: u64,
+  pub saved_at: u64,
+  pub date: String,
+  pub players: Players,
+  pub my_color: Color,
+  pub start_fen: String,
+  pub fen: String,
+  pub moves: Vec<SavedMove>,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub clock: Option<SavedClock>,
+}
+
+/// Version 1: the board, as serde wrote it, and the history.
+#[derive(Deserialize)]
+struct VersionOne {
+  board: Board,
+  history: Vec<HistoryEntry>,
+}
+
+/// Read a save file of any version, migrating older ones.
+///
+/// Parameters:
+/// `json`: &str
+///
+/// Return: Result<SaveFile, ChessError> (a parse error for bad JSON or unknown versions)
+pub fn read(json: &str) -> Result<SaveFile, ChessError> {
+  let value: Value = serde_json::from_str(json)?;
+
+  let version = match value.get("version") {
+    Some(version) => match version.as_u64() {
+      Some(version) => version,
+      None => return Err(ChessError::Parse(format!("Save file version must be a number, found {}.", version))),
+    },
+
+    // Older files don't have one
+    None if value.get("history").is_some() => 1,
+    None => 0,
+  };
+
+  match version {
+    0 => Ok(from_board(serde_json::from_value(value)?, &[])),
+    1 => {
+      let saved: VersionOne = serde_json::from_value(value)?;
+      Ok(from_board(saved.board, &saved.history))
+    },
+    SAVE_VERSION => Ok(serde_json::from_value(value)?),
+    newer => Err(ChessError::Parse(format!(
+      "Save file version {} is newer than this release can read (up to {}).", newer, SAVE_VERSION,
+    ))),
+  }
+}
+
+/// Migrate a board and its history from before the versioned format.
+fn from_board(board: Board, history: &[HistoryEntry]) -> SaveFile {
+  let fen = board.to_fen();
+  let now = helpers::now();
+
+  SaveFile{
+    version: SAVE_VERSION,
+    created_at: now,
+    saved_at: now,
+    date: helpers::today(),
+    players: Players{
+      white: String::from("?"),
+      black: String::from("?"),
+    },
+    my_color: board.get_my_color(),
+    start_fen: match history.first() {
+      Some(entry) => entry.fen_before.clone(),
+      None => fen.clone(),
+    },
+    fen,
+    moves: history.iter().map(|entry| SavedMove{
+      coordinates: entry.chess_move.to_string(),
+      san: entry.san.clone(),
+    }).collect(),
+    clock: None,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_read_versions() {
+    // Version 0, as the first releases wrote it
+    let mut board = Board::new(Color::Black);
+    assert!(board.make_move("E2", "E4", true).is_ok());
+
+    let save = read(&board.serialize()).unwrap();
+
+    assert_eq!(save.version, SAVE_VERSION);
+    assert_eq!(save.my_color, Color::Black);
+    assert_eq!(save.start_fen, board.to_fen());
+    assert!(save.moves.is_empty());
+
+    // The current version round trips
+    let json = serde_json::to_string(&save).unwrap();
+    assert_eq!(read(&json).unwrap(), save);
+  }
+
+  #[test]
+  fn test_read_errors() {
+    let bad = [
+      "not json",
+      "{\"version\": \"two\"}",
+      "{\"version\": 99}",
+      "{\"version\": 2, \"fen\": \"missing everything else\"}",
+      "{\"board\": 1}",
+    ];
+
+    for json in bad.iter() {
+      match read(json) {
+        Err(ChessError::Parse(_)) => (),
+        other => panic!("{}: {:?}", json, other),
+      };
+    }
+  }
+}
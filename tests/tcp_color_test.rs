@@ -0,0 +1,54 @@
+//
+extern crate rust_chess;
+
+//
+use rust_chess::board::Color;
+use rust_chess::client::*;
+use rust_chess::protocol::*;
+
+// thread
+use std::thread;
+use std::time::Duration;
+
+/// Connect to the host, giving it a moment to start listening.
+fn join(addr: &str) -> Client {
+  for _ in 0..50 {
+    match Client::new(addr) {
+      Ok(client) => return client,
+      Err(_) => thread::sleep(Duration::from_millis(100)),
+    };
+  }
+
+  panic!("Could not connect to {}", addr);
+}
+
+#[test]
+fn tcp_color_test() {
+  // The guest asks for white, the host doesn't mind
+  let guest = thread::spawn(|| {
+    let mut client = join("tcp://127.0.0.1:54346");
+
+    let color = client.negotiate_color("lev", Some(&ColorChoice::White)).unwrap();
+
+    (color, String::from(client.get_name()))
+  });
+
+  let mut host = Client::host("tcp://0.0.0.0:54346").unwrap();
+
+  assert_eq!(host.negotiate_color("host", None).unwrap(), Color::Black);
+  assert_eq!(host.get_name(), "lev");
+
+  assert_eq!(guest.join().unwrap(), (Color::White, String::from("host")));
+
+  // The host wants white whatever the guest asks for; names are cleaned up
+  let guest = thread::spawn(|| {
+    let mut client = join("tcp://127.0.0.1:54347");
+
+    client.negotiate_color("lev", Some(&ColorChoice::White)).unwrap()
+  });
+
+  let mut host = Client::host("tcp://0.0.0.0:54347").unwrap();
+
+  assert_eq!(host.negotiate_color("the host!", Some(&ColorChoice::White)).unwrap(), Color::White);
+  assert_eq!(guest.join().unwrap(), Color::Black);
+}